@@ -0,0 +1,236 @@
+//! `mdtpp.toml`, which describes the documents to build as named targets.
+//!
+//! ```toml
+//! [targets.readme-static]
+//! input = "README.template.md"
+//! output = "README.md"
+//! mode = "static"
+//! processors = ["link-or-include", "include"]
+//! variables = { edition = "pro" }
+//!
+//! [modes.print]
+//! tags = { "link or include" = "inline", include = "inline" }
+//! flags = ["print"]
+//!
+//! [templates]
+//! link = "See [{title}]({link})."
+//! details-file = "templates/details.md"
+//! ```
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use serde::{de, Deserialize, Deserializer};
+use crate::error::{Error, ErrorKind, Location};
+use crate::mode::{BuildMode, Strategy};
+use crate::processor::read_to_string;
+use crate::Templates;
+
+/// the name of the configuration file read by default.
+pub const CONFIG_FILE: &str = "mdtpp.toml";
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    /// the targets by their names.
+    #[serde(default)]
+    pub targets: BTreeMap<String, Target>,
+    /// the build modes defined in addition to the built-in ones, by their names.
+    #[serde(default)]
+    pub modes: BTreeMap<String, Mode>,
+    #[serde(default)]
+    pub templates: TemplateFiles,
+}
+
+/// a document or a directory to build, and how to build it.
+/// every path is relative to the directory of the configuration file.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Target {
+    pub input: Option<PathBuf>,
+    pub output: Option<PathBuf>,
+    pub input_dir: Option<PathBuf>,
+    pub output_dir: Option<PathBuf>,
+    /// globs of the files under `input_dir` which are not written to `output_dir`.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// the name of the build mode, which is built-in or defined in `modes`.
+    pub mode: Option<String>,
+    /// built-in preprocessors to run, in order. every built-in preprocessor runs if omitted.
+    pub processors: Option<Vec<String>>,
+    /// built-in preprocessors not to run.
+    #[serde(default)]
+    pub disable: Vec<String>,
+    /// variables for `{{var|name}}`. values other than strings are written as in TOML.
+    #[serde(default, deserialize_with = "deserialize_variables")]
+    pub variables: BTreeMap<String, String>,
+}
+
+/// a build mode defined in the configuration file.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields)]
+pub struct Mode {
+    /// how the tags are rendered, by the names of tags: `link`, `inline`, `details-collapsed` or `omit`.
+    /// the tags not listed are rendered inline. `link-or-include` is accepted as `link or include`.
+    #[serde(default, deserialize_with = "deserialize_strategies")]
+    pub tags: BTreeMap<String, Strategy>,
+    /// extra flags, which can be tested by `{{if mode.<flag>}}`.
+    #[serde(default)]
+    pub flags: BTreeSet<String>,
+}
+
+impl Mode {
+    #[must_use]
+    pub fn build_mode(&self, name: &str) -> BuildMode {
+        BuildMode::new(name, self.tags.clone(), self.flags.clone())
+    }
+}
+
+/// the templates given as text, or as paths of the files which contain them.
+/// see [`Templates`] for the placeholders.
+#[derive(Deserialize, Debug, Clone, Default)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct TemplateFiles {
+    pub link: Option<String>,
+    pub link_file: Option<PathBuf>,
+    pub details: Option<String>,
+    pub details_file: Option<PathBuf>,
+}
+
+impl TemplateFiles {
+    /// reads the templates. the paths are relative to `config_dir`, and the templates not given are the default ones.
+    /// the last line terminator of a template file is removed, because the tag does not end with a line terminator.
+    ///
+    /// # Errors
+    /// fails if a template is given both as text and as a file, or the file can not be read.
+    pub fn load(&self, config_dir: &Path) -> Result<Templates, Error> {
+        let load = |name: &str, text: &Option<String>, file: &Option<PathBuf>| match (text, file) {
+            (Some(_), Some(_)) => Err(Error::from(ErrorKind::InvalidArgument(format!("Specify either `{name}` or `{name}-file` of templates")))),
+            (Some(text), None) => Ok(Some(text.clone())),
+            (None, Some(file)) => {
+                let text = read_to_string(&config_dir.join(file))?;
+                let text = text.strip_suffix('\n').map_or(text.as_str(), |text| text.strip_suffix('\r').unwrap_or(text));
+                Ok(Some(text.to_string()))
+            }
+            (None, None) => Ok(None),
+        };
+        let default = Templates::default();
+
+        Ok(Templates {
+            link: load("link", &self.link, &self.link_file)?.unwrap_or(default.link),
+            details: load("details", &self.details, &self.details_file)?.unwrap_or(default.details),
+        })
+    }
+}
+
+impl Config {
+    /// reads the configuration file at `path`.
+    ///
+    /// # Errors
+    /// fails if the file can not be read, or it is not a valid configuration.
+    pub fn load(path: &Path) -> Result<Self, Error> {
+        let content = read_to_string(path)?;
+        toml::from_str(&content).map_err(|e| {
+            let location = e.span().map(|span| Location::from_offset(path, &content, span.start));
+            let error = Error::from(ErrorKind::InvalidConfig { path: path.to_path_buf(), message: e.message().to_string() });
+            match location {
+                Some(location) => error.at(|| location),
+                None => error,
+            }
+        })
+    }
+}
+
+fn deserialize_variables<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<String, String>, D::Error> {
+    let variables = BTreeMap::<String, toml::Value>::deserialize(deserializer)?;
+
+    Ok(variables.into_iter()
+        .map(|(name, value)| match value {
+            toml::Value::String(value) => (name, value),
+            value => (name, value.to_string()),
+        })
+        .collect())
+}
+
+fn deserialize_strategies<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<String, Strategy>, D::Error> {
+    let strategies = BTreeMap::<String, Strategy>::deserialize(deserializer)?;
+
+    strategies.into_iter()
+        .map(|(name, strategy)| {
+            let tag = BuildMode::tag_named(&name).ok_or_else(|| de::Error::custom(format!(
+                "unknown tag `{name}`: the tags rendered by modes are {tags}",
+                tags = BuildMode::TAGS.map(|tag| format!("`{tag}`")).join(", "),
+            )))?;
+            Ok((tag.to_string(), strategy))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::TempDir;
+    use super::*;
+
+    #[test]
+    fn loads_targets() {
+        let dir = TempDir::new();
+        let path = dir.write(CONFIG_FILE, "[targets.readme]\ninput = \"README.template.md\"\noutput = \"README.md\"\nmode = \"static\"\n");
+        let config = Config::load(&path).unwrap();
+        let target = &config.targets["readme"];
+        assert_eq!(target.input.as_deref(), Some(Path::new("README.template.md")));
+        assert_eq!(target.output.as_deref(), Some(Path::new("README.md")));
+        assert_eq!(target.mode.as_deref(), Some("static"));
+        assert!(target.input_dir.is_none());
+        assert!(config.modes.is_empty());
+    }
+
+    #[test]
+    fn locates_invalid_fields() {
+        let dir = TempDir::new();
+        let path = dir.write(CONFIG_FILE, "[targets.readme]\ninput = \"a.md\"\noutptu = \"b.md\"\n");
+        let e = Config::load(&path).unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::InvalidConfig { .. }), "{e}");
+        let location = e.location().unwrap();
+        assert_eq!((location.file.as_path(), location.line, location.column), (path.as_path(), 3, 1));
+    }
+
+    #[test]
+    fn reads_strategies_by_tag_names() {
+        let config: Config = toml::from_str("[modes.print]\ntags = { link-or-include = \"link\", include = \"omit\" }\n").unwrap();
+        let mode = config.modes["print"].build_mode("print");
+        assert_eq!(mode.strategy("link or include"), Strategy::Link);
+        assert_eq!(mode.strategy("include"), Strategy::Omit);
+    }
+
+    #[test]
+    fn rejects_unknown_tags_in_strategies() {
+        let e = toml::from_str::<Config>("[modes.print]\ntags = { includ = \"link\" }\n").unwrap_err();
+        assert!(e.message().starts_with("unknown tag `includ`"), "{e}");
+    }
+
+    #[test]
+    fn loads_templates_from_text_or_files() {
+        let dir = TempDir::new();
+        dir.write("templates/details.md", "<details>{content}</details>\r\n");
+        let templates = TemplateFiles {
+            link: Some("See {link}".to_string()),
+            details_file: Some(PathBuf::from("templates/details.md")),
+            ..TemplateFiles::default()
+        };
+        assert_eq!(templates.load(dir.path()).unwrap(), Templates {
+            link: "See {link}".to_string(),
+            details: "<details>{content}</details>".to_string(),
+        });
+        assert_eq!(TemplateFiles::default().load(dir.path()).unwrap(), Templates::default());
+    }
+
+    #[test]
+    fn rejects_templates_given_twice() {
+        let templates = TemplateFiles {
+            link: Some("See {link}".to_string()),
+            link_file: Some(PathBuf::from("link.md")),
+            ..TemplateFiles::default()
+        };
+        let e = templates.load(Path::new(".")).unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::InvalidArgument(_)), "{e}");
+    }
+}
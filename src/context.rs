@@ -0,0 +1,292 @@
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+use crate::error::{Error, ErrorKind, Location};
+use crate::front_matter::front_matter;
+use crate::mode::BuildMode;
+use crate::pipeline::Pipeline;
+use crate::source_map::{Edit, SourceMap};
+use crate::template::Templates;
+
+pub struct BuildContext<'ctx> {
+    mode: &'ctx BuildMode,
+    input_file: &'ctx Path,
+    /// the context of the file which includes `input_file`. this is `None` for the root document.
+    parent: Option<&'ctx Self>,
+    /// preprocessors which are run over the root document and the included files.
+    pipeline: &'ctx Pipeline,
+    /// files read while expanding tags. only the one of the root context is used.
+    dependencies: RefCell<BTreeSet<PathBuf>>,
+    /// the number of files included. only the one of the root context is used.
+    includes: Cell<usize>,
+    /// how the content of `input_file` has been rewritten, to locate tags in it.
+    source_map: RefCell<SourceMap>,
+    /// whether problems are collected instead of stopping at the first one.
+    checking: bool,
+    /// problems found in `input_file` and the files included from it, while checking.
+    problems: RefCell<Vec<Error>>,
+    /// variables given to the build, such as `--define`. only the one of the root context is used.
+    variables: BTreeMap<String, String>,
+    /// variables defined in the front matter of `input_file`.
+    front_matter: RefCell<Vec<(String, String)>>,
+    /// the build modes which can be chosen by tags, other than the built-in ones. only the one of the root context is
+    /// used.
+    modes: BTreeMap<String, BuildMode>,
+    /// templates of the links and the collapsed contents, or `None` for the default ones. only the one of the root
+    /// context is used.
+    templates: Option<Templates>,
+}
+
+impl<'ctx> BuildContext<'ctx> {
+    /// creates a context for the root document `input_file`.
+    /// relative paths in tags are resolved from the directory of `input_file`.
+    #[must_use]
+    pub const fn new(mode: &'ctx BuildMode, input_file: &'ctx Path, pipeline: &'ctx Pipeline) -> Self {
+        Self {
+            mode,
+            input_file,
+            parent: None,
+            pipeline,
+            dependencies: RefCell::new(BTreeSet::new()),
+            includes: Cell::new(0),
+            source_map: RefCell::new(SourceMap::new()),
+            checking: false,
+            problems: RefCell::new(vec![]),
+            variables: BTreeMap::new(),
+            front_matter: RefCell::new(vec![]),
+            modes: BTreeMap::new(),
+            templates: None,
+        }
+    }
+
+    /// gives `variables` to the build. they take precedence over the variables in front matter.
+    #[must_use]
+    pub fn with_variables(self, variables: BTreeMap<String, String>) -> Self {
+        Self { variables, ..self }
+    }
+
+    /// makes tags which can not be expanded reported by [`report`](Self::report) and removed,
+    /// so that every problem in the document is found at once.
+    #[must_use]
+    pub fn checking(self) -> Self {
+        Self { checking: true, ..self }
+    }
+
+    /// makes `modes` selectable by tags such as `{{link or include|./a.md|mode=print}}`.
+    /// they take precedence over the built-in modes of the same name.
+    #[must_use]
+    pub fn with_modes(self, modes: impl IntoIterator<Item = BuildMode>) -> Self {
+        let modes = modes.into_iter().map(|mode| (mode.name().to_string(), mode)).collect();
+        Self { modes, ..self }
+    }
+
+    /// renders the links and the collapsed contents with `templates`.
+    #[must_use]
+    pub fn with_templates(self, templates: Templates) -> Self {
+        Self { templates: Some(templates), ..self }
+    }
+
+    #[must_use]
+    pub fn templates(&self) -> Cow<'_, Templates> {
+        self.root().templates.as_ref().map_or_else(|| Cow::Owned(Templates::default()), Cow::Borrowed)
+    }
+
+    #[must_use]
+    pub const fn is_checking(&self) -> bool {
+        self.checking
+    }
+
+    #[must_use]
+    pub const fn mode(&self) -> &'ctx BuildMode {
+        self.mode
+    }
+
+    /// looks up the build mode named `name`, which is given by [`with_modes`](Self::with_modes) or built-in.
+    ///
+    /// # Errors
+    /// fails if there is no such mode.
+    pub fn lookup_mode(&self, name: &str) -> Result<BuildMode, Error> {
+        BuildMode::lookup(name, &self.root().modes)
+    }
+
+    /// the file whose content is being processed.
+    #[must_use]
+    pub const fn input_file(&self) -> &'ctx Path {
+        self.input_file
+    }
+
+    #[must_use]
+    pub const fn pipeline(&self) -> &'ctx Pipeline {
+        self.pipeline
+    }
+
+    /// creates a context for expanding tags in `included_file`, which was included from this context.
+    /// relative paths in the included file are resolved from the included file itself.
+    ///
+    /// # Errors
+    /// fails if `included_file` is already being expanded, because expanding it again never terminates.
+    pub fn enter<'a>(&'a self, included_file: &'a Path) -> Result<BuildContext<'a>, Error> {
+        if self.ancestors().any(|ctx| same_file(ctx.input_file, included_file)) {
+            return Err(ErrorKind::IncludeCycle { chain: self.include_chain(included_file) }.into())
+        }
+
+        Ok(BuildContext {
+            mode: self.mode,
+            input_file: included_file,
+            parent: Some(self),
+            pipeline: self.pipeline,
+            dependencies: RefCell::new(BTreeSet::new()),
+            includes: Cell::new(0),
+            source_map: RefCell::new(SourceMap::new()),
+            checking: self.checking,
+            problems: RefCell::new(vec![]),
+            variables: BTreeMap::new(),
+            front_matter: RefCell::new(vec![]),
+            modes: BTreeMap::new(),
+            templates: None,
+        })
+    }
+
+    /// remembers that the output depends on `path`.
+    /// `path` may not exist, so that the output is rebuilt when it is created.
+    pub fn record_dependency(&self, path: &Path) {
+        self.root().dependencies.borrow_mut().insert(path.to_path_buf());
+    }
+
+    /// counts a file included into the output. a file included twice is counted twice.
+    pub fn record_include(&self) {
+        let includes = &self.root().includes;
+        includes.set(includes.get() + 1);
+    }
+
+    /// the number of files included while expanding tags in the root document.
+    #[must_use]
+    pub fn includes(&self) -> usize {
+        self.root().includes.get()
+    }
+
+    /// the value of the variable `name`.
+    /// the variables given to the build are looked up first, then the front matter of `input_file`, and then the
+    /// front matter of the files including it, innermost first.
+    #[must_use]
+    pub fn variable(&self, name: &str) -> Option<String> {
+        self.root().variables.get(name).cloned().or_else(|| {
+            self.ancestors().find_map(|ctx| {
+                ctx.front_matter.borrow().iter().find(|(key, _)| key == name).map(|(_, value)| value.clone())
+            })
+        })
+    }
+
+    /// defines the entries of the front matter of `content`, which is the whole content of `input_file`, as variables.
+    pub(crate) fn read_front_matter(&self, content: &str) {
+        if let Some(front_matter) = front_matter(content) {
+            *self.front_matter.borrow_mut() = front_matter.entries;
+        }
+    }
+
+    /// remembers a problem found while checking.
+    pub fn report(&self, problem: Error) {
+        self.problems.borrow_mut().push(problem);
+    }
+
+    /// takes the problems reported to this context.
+    /// the problems in an included file are moved to the context which includes it, when expanding it finishes.
+    #[must_use]
+    pub fn take_problems(&self) -> Vec<Error> {
+        self.problems.take()
+    }
+
+    /// remembers `content` as the text of `input_file` before any tag is expanded.
+    pub(crate) fn start_source(&self, content: &str) {
+        self.source_map.borrow_mut().start(content);
+    }
+
+    /// records that a pass of a preprocessor made `edits` on the content.
+    pub(crate) fn record_edits(&self, edits: Vec<Edit>) {
+        self.source_map.borrow_mut().record(edits);
+    }
+
+    /// locates `offset` of `content`, which is the content of `input_file` rewritten by the preceding preprocessors.
+    #[must_use]
+    pub fn locate(&self, content: &str, offset: usize) -> Location {
+        self.source_map.borrow().locate(self.input_file, content, offset)
+    }
+
+    /// the root document and the files read while expanding tags in it, in no particular order.
+    #[must_use]
+    pub fn dependencies(&self) -> Vec<PathBuf> {
+        let root = self.root();
+        std::iter::once(root.input_file.to_path_buf())
+            .chain(root.dependencies.borrow().iter().cloned())
+            .collect()
+    }
+
+    fn root(&self) -> &Self {
+        self.ancestors().last().unwrap_or(self)
+    }
+
+    /// iterates over this context and the contexts which include it, innermost first.
+    fn ancestors(&self) -> impl Iterator<Item = &BuildContext<'ctx>> {
+        std::iter::successors(Some(self), |ctx| ctx.parent)
+    }
+
+    /// lists the stack of files being expanded, followed by `next`.
+    /// the paths are relative to the directory of the root document if possible.
+    fn include_chain(&self, next: &Path) -> Vec<PathBuf> {
+        let root_dir = self.root().input_file.parent()
+            .map(|dir| if dir.as_os_str().is_empty() { Path::new(".") } else { dir })
+            .and_then(|dir| dir.canonicalize().ok())
+            .unwrap_or_default();
+        let mut chain = self.ancestors().map(|ctx| ctx.input_file).collect::<Vec<_>>();
+        chain.reverse();
+        chain.push(next);
+
+        chain.into_iter()
+            .map(|path| path.strip_prefix(&root_dir).unwrap_or(path).to_path_buf())
+            .collect()
+    }
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => a == b,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing::{expand, TempDir};
+    use super::*;
+
+    fn cycle(result: Result<String, Error>) -> String {
+        let e = result.unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::IncludeCycle { .. }), "{e}");
+        e.kind().to_string()
+    }
+
+    #[test]
+    fn detects_files_including_themselves() {
+        let dir = TempDir::new();
+        let input_file = dir.write("a.md", "{{include|./a.md}}\n");
+        assert_eq!(cycle(expand(&input_file, "static")), "include cycle detected: a.md -> a.md");
+    }
+
+    #[test]
+    fn detects_indirect_cycles() {
+        let dir = TempDir::new();
+        let input_file = dir.write("index.md", "{{include|./a.md}}\n");
+        dir.write("a.md", "{{include|./sub/b.md}}\n");
+        dir.write("sub/b.md", "{{include|../a.md}}\n");
+        assert_eq!(cycle(expand(&input_file, "static")), "include cycle detected: index.md -> a.md -> sub/b.md -> a.md");
+    }
+
+    #[test]
+    fn includes_a_file_twice_without_a_cycle() {
+        let dir = TempDir::new();
+        let input_file = dir.write("index.md", "{{include|./a.md}}{{include|./a.md}}\n");
+        dir.write("a.md", "A");
+        assert_eq!(expand(&input_file, "static").unwrap(), "AA\n");
+    }
+}
@@ -0,0 +1,227 @@
+use std::fmt::{Display, Formatter};
+use std::io::ErrorKind as IoErrorKind;
+use std::path::{Path, PathBuf};
+use itertools::Itertools;
+
+/// position of the tag which caused an [`Error`]. locations are ordered by the file, the line and the column.
+#[derive(Clone, Eq, PartialEq, Ord, PartialOrd, Debug)]
+pub struct Location {
+    pub file: PathBuf,
+    /// 1-origin line number
+    pub line: usize,
+    /// 1-origin column number, counted in characters
+    pub column: usize,
+}
+
+impl Location {
+    /// computes the line and column of `offset`, which is a byte offset in `content` read from `file`.
+    #[must_use]
+    pub fn from_offset(file: &Path, content: &str, offset: usize) -> Self {
+        let before = &content[..offset];
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+
+        Self {
+            file: file.to_path_buf(),
+            line: before.matches('\n').count() + 1,
+            column: before[line_start..].chars().count() + 1,
+        }
+    }
+
+    /// moves this location by the length of `preceding`, which is the text placed before the part of the file
+    /// on which this location was computed.
+    fn after(self, preceding: &str) -> Self {
+        let preceding_lines = preceding.matches('\n').count();
+        let column = if self.line == 1 {
+            let line_start = preceding.rfind('\n').map_or(0, |newline| newline + 1);
+            self.column + preceding[line_start..].chars().count()
+        } else {
+            self.column
+        };
+
+        Self {
+            line: self.line + preceding_lines,
+            column,
+            ..self
+        }
+    }
+}
+
+impl Display for Location {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{file}:{line}:{column}", file = self.file.display(), line = self.line, column = self.column)
+    }
+}
+
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum ErrorKind {
+    InvalidArgument(String),
+    MissingFile {
+        path: PathBuf,
+    },
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    /// the content of the file is not UTF-8.
+    InvalidUtf8 {
+        path: PathBuf,
+    },
+    /// the path has to be embedded to the output, but it can not be represented in UTF-8.
+    NonUtf8Path {
+        path: PathBuf,
+    },
+    BadTagSyntax {
+        tag: String,
+        expected: &'static str,
+    },
+    /// no preprocessor expands the tag of the name.
+    UnknownTag {
+        name: String,
+    },
+    IncludeCycle {
+        chain: Vec<PathBuf>,
+    },
+    /// a start marker is not closed, or an end marker is not opened.
+    UnbalancedMarker {
+        marker: String,
+    },
+    /// two regions have the same name in a file.
+    DuplicateRegion {
+        name: String,
+    },
+    /// `lines`, `from` or `to` option selects no line of the file.
+    EmptySelection {
+        path: PathBuf,
+        selection: String,
+    },
+    /// the region or the heading to include is not found.
+    MissingRegion {
+        path: PathBuf,
+        /// `None` for the region without name.
+        name: Option<String>,
+    },
+    /// there is no build mode of the name.
+    UnknownMode {
+        name: String,
+        available: Vec<String>,
+    },
+    /// the variable is not defined, and the tag has no default value.
+    UndefinedVariable {
+        name: String,
+    },
+    /// the configuration file is malformed.
+    InvalidConfig {
+        path: PathBuf,
+        message: String,
+    },
+    /// `--check` found problems, which are reported separately.
+    CheckFailed {
+        problems: usize,
+    },
+    /// `--diff` found outputs which differ from the existing files.
+    OutdatedOutput {
+        files: usize,
+    },
+}
+
+impl ErrorKind {
+    /// classifies the I/O error which occurred on accessing `path`.
+    #[must_use]
+    pub fn from_io(path: &Path, source: std::io::Error) -> Self {
+        match source.kind() {
+            IoErrorKind::NotFound => Self::MissingFile { path: path.to_path_buf() },
+            IoErrorKind::InvalidData => Self::InvalidUtf8 { path: path.to_path_buf() },
+            _ => Self::Io { path: path.to_path_buf(), source },
+        }
+    }
+}
+
+impl Display for ErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::InvalidArgument(message) => f.write_str(message),
+            Self::MissingFile { path } => write!(f, "file not found: {path}", path = path.display()),
+            Self::Io { path, source } => write!(f, "{path}: {source}", path = path.display()),
+            Self::InvalidUtf8 { path } => write!(f, "{path}: the content is not valid UTF-8", path = path.display()),
+            Self::NonUtf8Path { path } => write!(f, "path is not valid UTF-8: {path}", path = path.display()),
+            Self::BadTagSyntax { tag, expected } => write!(f, "malformed `{tag}` tag: expected {expected}"),
+            Self::UnknownTag { name } => write!(f, "unknown tag `{name}`"),
+            Self::IncludeCycle { chain } => write!(f, "include cycle detected: {chain}", chain = chain.iter().map(|path| path.display()).join(" -> ")),
+            Self::UnbalancedMarker { marker } => write!(f, "`{marker}` has no counterpart"),
+            Self::DuplicateRegion { name } => write!(f, "region `{name}` is defined more than once"),
+            Self::EmptySelection { path, selection } => write!(f, "`{selection}` selects no line of {path}", path = path.display()),
+            Self::MissingRegion { path, name: Some(name) } => write!(f, "{path} has neither region nor heading anchor named `{name}`", path = path.display()),
+            Self::MissingRegion { path, name: None } => write!(f, "{path} has no region marked with `<!-- START -->` and `<!-- END -->`", path = path.display()),
+            Self::UnknownMode { name, available } => write!(f, "unknown build mode `{name}`: available modes are {available}", available = available.join(", ")),
+            Self::UndefinedVariable { name } => write!(f, "variable `{name}` is not defined"),
+            Self::InvalidConfig { path, message } => write!(f, "invalid configuration in {path}: {message}", path = path.display()),
+            Self::CheckFailed { problems } => write!(f, "found {problems} problem(s)"),
+            Self::OutdatedOutput { files } => write!(f, "{files} output file(s) are out of date"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    /// where the tag which caused this error is. this is `None` if the error is not caused by a tag.
+    location: Option<Location>,
+}
+
+impl Error {
+    #[must_use]
+    pub const fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    #[must_use]
+    pub const fn location(&self) -> Option<&Location> {
+        self.location.as_ref()
+    }
+
+    /// attaches the location of the offending tag, unless more precise one is already attached.
+    #[must_use]
+    pub fn at(mut self, location: impl FnOnce() -> Location) -> Self {
+        if self.location.is_none() {
+            self.location = Some(location());
+        }
+
+        self
+    }
+
+    /// corrects the location computed on a part of `file` which is preceded by `preceding`.
+    pub(crate) fn after(mut self, file: &Path, preceding: &str) -> Self {
+        self.location = self.location.map(|location| if location.file == file {
+            location.after(preceding)
+        } else {
+            location
+        });
+
+        self
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Self { kind, location: None }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{location}: {kind}", kind = self.kind),
+            None => write!(f, "{kind}", kind = self.kind),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,88 @@
+//! YAML-like front matter at the top of Markdown files. only flat `key: value` lines are understood.
+
+use std::ops::Range;
+
+pub struct FrontMatter {
+    pub entries: Vec<(String, String)>,
+    /// byte range of the front matter, including the delimiters and the last line terminator.
+    pub span: Range<usize>,
+}
+
+impl FrontMatter {
+    /// the value of `key`, without quotes.
+    #[must_use]
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.entries.iter().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+
+    /// whether `key` is set to `true` or `yes`.
+    #[must_use]
+    pub fn flag(&self, key: &str) -> bool {
+        self.get(key).is_some_and(|value| matches!(value, "true" | "yes"))
+    }
+}
+
+/// reads the front matter delimited by `---` lines at the top of `text`.
+///
+/// every line in between must be blank, a `#` comment or a `key: value` entry, and there must be at least one entry.
+/// otherwise the first `---` is a thematic break rather than front matter.
+#[must_use]
+pub fn front_matter(text: &str) -> Option<FrontMatter> {
+    let mut lines = crate::markdown::lines(text);
+    if lines.next()?.text != "---" {
+        return None
+    }
+
+    let mut entries = vec![];
+    for line in lines {
+        if line.text == "---" || line.text == "..." {
+            return (!entries.is_empty()).then_some(FrontMatter { entries, span: 0..line.span.end })
+        }
+
+        let trimmed = line.text.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue
+        }
+
+        let (key, value) = line.text.split_once(':')?;
+        let key = key.trim();
+        if key.is_empty() || !key.chars().all(|c| c.is_alphanumeric() || c == '_' || c == '-') {
+            return None
+        }
+
+        let value = value.trim();
+        let unquoted = value.strip_prefix('"').and_then(|value| value.strip_suffix('"'))
+            .or_else(|| value.strip_prefix('\'').and_then(|value| value.strip_suffix('\'')))
+            .unwrap_or(value);
+        entries.push((key.to_string(), unquoted.to_string()));
+    }
+
+    None
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reads_entries() {
+        let front_matter = front_matter("---\ntitle: \"Guide\"\n\n# comment\npartial: yes\n---\n\nBody\n").unwrap();
+        assert_eq!(front_matter.get("title"), Some("Guide"));
+        assert!(front_matter.flag("partial"));
+        assert_eq!(front_matter.get("missing"), None);
+        assert_eq!(front_matter.span, 0..47);
+    }
+
+    #[test]
+    fn needs_a_closing_delimiter() {
+        assert!(front_matter("---\ntitle: Guide\n").is_none());
+        assert!(front_matter("title: Guide\n---\n").is_none());
+    }
+
+    #[test]
+    fn ignores_thematic_breaks() {
+        assert!(front_matter("---\n\nSome intro.\n\n---\n\nBody\n").is_none());
+        assert!(front_matter("---\nSee the notes: below\n---\n").is_none());
+        assert!(front_matter("---\n\n---\n").is_none());
+    }
+}
@@ -0,0 +1,40 @@
+#![forbid(unsafe_code)]
+#![deny(clippy::all, clippy::collection_is_never_read)]
+#![warn(clippy::pedantic, clippy::nursery)]
+
+//! expands template tags such as `{{include|./path/to/file.md}}` in Markdown documents.
+
+mod context;
+mod error;
+mod front_matter;
+mod markdown;
+mod mode;
+mod pipeline;
+mod processor;
+mod region;
+mod snippet;
+mod source_map;
+mod template;
+#[cfg(test)]
+mod testing;
+mod tree;
+pub mod config;
+pub mod tag;
+
+pub use context::BuildContext;
+pub use error::{Error, ErrorKind, Location};
+pub use mode::{BuildMode, Strategy};
+pub use pipeline::Pipeline;
+pub use processor::{AlwaysInclude, Conditional, LinkOrInclude, PreProcessor, Variable};
+pub use template::Templates;
+pub use tree::{walk_tree, EntryKind, TreeEntry};
+
+/// runs the pipeline of `context` over `input`, which is the content of the root document `context.input_file()`.
+/// the included files are expanded by the same pipeline recursively.
+///
+/// # Errors
+/// fails if any of the tags in `input` or in the included files can not be expanded.
+pub fn process(input: String, context: &BuildContext<'_>) -> Result<String, Error> {
+    context.read_front_matter(&input);
+    context.pipeline().run(context, input).map(|output| tag::unescape(&output))
+}
@@ -0,0 +1,96 @@
+//! the logger which writes the diagnostics of the [`log`] crate to stderr, so that stdout stays clean.
+
+use std::fmt::Write as _;
+use std::io::Write as _;
+use log::{Level, LevelFilter, Log, Metadata, Record, SetLoggerError};
+use strum::EnumString;
+
+#[derive(EnumString, Copy, Clone, Eq, PartialEq, Debug, Default)]
+#[strum(serialize_all = "camelCase")]
+pub enum LogFormat {
+    /// `level: message` for humans.
+    #[default]
+    Text,
+    /// a JSON object per line, which has `level`, `target` and `message`.
+    Json,
+}
+
+pub struct Logger {
+    level: LevelFilter,
+    format: LogFormat,
+}
+
+impl Logger {
+    #[must_use]
+    pub const fn new(level: LevelFilter, format: LogFormat) -> Self {
+        Self { level, format }
+    }
+
+    /// makes this the global logger.
+    ///
+    /// # Errors
+    /// fails if another logger is already installed.
+    pub fn install(self) -> Result<(), SetLoggerError> {
+        let level = self.level;
+        log::set_boxed_logger(Box::new(self))?;
+        log::set_max_level(level);
+
+        Ok(())
+    }
+}
+
+impl Log for Logger {
+    fn enabled(&self, metadata: &Metadata<'_>) -> bool {
+        metadata.level() <= self.level
+    }
+
+    fn log(&self, record: &Record<'_>) {
+        if !self.enabled(record.metadata()) {
+            return
+        }
+
+        let level = match record.level() {
+            Level::Error => "error",
+            Level::Warn => "warning",
+            Level::Info => "info",
+            Level::Debug => "debug",
+            Level::Trace => "trace",
+        };
+        let line = match self.format {
+            LogFormat::Text => format!("{level}: {message}", message = record.args()),
+            LogFormat::Json => format!(
+                r#"{{"level":{level},"target":{target},"message":{message}}}"#,
+                level = json_string(level),
+                target = json_string(record.target()),
+                message = json_string(&record.args().to_string()),
+            ),
+        };
+        // a line is written at once, so that the lines of threads are not mixed up.
+        let _ = writeln!(std::io::stderr().lock(), "{line}");
+    }
+
+    fn flush(&self) {
+        let _ = std::io::stderr().flush();
+    }
+}
+
+/// quotes `s` as a JSON string.
+fn json_string(s: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => {
+                let _ = write!(quoted, "\\u{code:04x}", code = u32::from(c));
+            }
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+
+    quoted
+}
@@ -2,194 +2,666 @@
 #![deny(clippy::all, clippy::collection_is_never_read)]
 #![warn(clippy::pedantic, clippy::nursery)]
 
+use std::collections::BTreeMap;
 use std::fs::File;
-use std::io::{BufReader, BufWriter, Read, Write};
-use std::ops::Index;
+use std::io::{BufWriter, Read, Write};
+use std::ops::AddAssign;
 use std::path::{Path, PathBuf};
-use std::str::FromStr;
-use clap::Parser;
-use regex::{Captures, Regex};
-use strum::EnumString;
+use std::process::ExitCode;
+use std::time::Duration;
+use clap::{ArgAction, Parser, Subcommand};
+use glob::Pattern;
 use itertools::Itertools;
+use log::LevelFilter;
+use similar::TextDiff;
+use markdown_template_preprocessor::{process, walk_tree, BuildContext, BuildMode, EntryKind, Error, ErrorKind, Pipeline, Templates, TreeEntry};
+use markdown_template_preprocessor::config::{Config, Target, CONFIG_FILE};
+use crate::logger::{LogFormat, Logger};
+use crate::watch::Watcher;
+
+mod logger;
+mod watch;
+
+/// the path which means the standard input or output.
+const STDIO: &str = "-";
 
 #[derive(Parser, Debug)]
+#[command(args_conflicts_with_subcommands = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
+    #[command(flatten)]
+    args: Args,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// build the targets described in the configuration file. the options override the ones of the targets.
+    Build {
+        /// the name of the target to build. every target is built if omitted.
+        #[clap(long)]
+        target: Option<String>,
+        /// the configuration file, whose relative paths are resolved from the directory of it.
+        #[clap(long, value_name = "PATH", default_value = CONFIG_FILE)]
+        config: PathBuf,
+        #[command(flatten)]
+        args: Args,
+    },
+}
+
+#[derive(clap::Args, Clone, Debug)]
+#[allow(clippy::struct_excessive_bools)]
 struct Args {
+    /// the Markdown file to preprocess. `-` reads the standard input.
     #[clap(short, long)]
-    input_file: PathBuf,
+    input_file: Option<PathBuf>,
+    /// `dynamic`, `static`, `spoiler`, or a mode defined in the configuration file. the configuration file is
+    /// `mdtpp.toml` in the current directory, or the one given by `build --config`.
     #[clap(short = 'm', long)]
-    build_mode: BuildMode,
+    build_mode: Option<String>,
+    /// the file to write the result. `-` writes to the standard output.
     #[clap(short, long)]
-    output_file: PathBuf,
+    output_file: Option<PathBuf>,
+    /// the directory from which the paths in the standard input are resolved. defaults to the current directory.
+    #[clap(long)]
+    base_dir: Option<PathBuf>,
+    /// preprocess every Markdown file under this directory, and copy the other files.
+    #[clap(long, conflicts_with = "input_file")]
+    input_dir: Option<PathBuf>,
+    /// write the files under `--input-dir` to this directory, keeping the directory layout.
+    #[clap(long, conflicts_with = "output_file")]
+    output_dir: Option<PathBuf>,
+    /// glob of files under `--input-dir` which are only included from other files, so are not written to
+    /// `--output-dir`. can be specified multiple times. Markdown files with `partial: true` in front matter are
+    /// skipped too.
+    #[clap(long)]
+    exclude: Vec<String>,
+    /// define a variable for `{{var|name}}`, as `name=value`. can be specified multiple times.
+    #[clap(short = 'D', long, value_name = "NAME=VALUE", value_parser = parse_definition)]
+    define: Vec<(String, String)>,
+    /// built-in preprocessors to run, in order. every built-in preprocessor runs if omitted.
+    #[clap(long, value_delimiter = ',')]
+    processors: Option<Vec<String>>,
+    /// built-in preprocessor not to run. can be specified multiple times.
+    #[clap(long)]
+    disable: Vec<String>,
+    /// write a Makefile-style dependency file, which lists the files read to build each output.
+    #[clap(long, value_name = "PATH")]
+    dep_file: Option<PathBuf>,
+    /// keep running, and rebuild the outputs when the input files or the files included from them change.
+    #[clap(long)]
+    watch: bool,
+    /// interval in milliseconds to check changes of files in `--watch` mode.
+    #[clap(long, default_value_t = 500, requires = "watch")]
+    poll_interval: u64,
+    /// report only errors.
+    #[clap(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// report the included files too. `-vv` reports how the paths are resolved as well.
+    #[clap(short, long, action = ArgAction::Count)]
+    verbose: u8,
+    /// report every problem in the tags, such as missing files or broken markers, without writing the output.
+    /// `--output-file` and `--output-dir` may be omitted.
+    #[clap(long, conflicts_with = "watch")]
+    check: bool,
+    /// compare the outputs with the existing files without writing them, and print the differences as unified diff.
+    /// fails if any of them is out of date.
+    #[clap(long, visible_alias = "verify", conflicts_with_all = ["watch", "check"])]
+    diff: bool,
+    /// format of the messages written to stderr: `text` or `json`.
+    #[clap(long, default_value = "text")]
+    log_format: LogFormat,
+}
+
+/// the build modes and the templates in the configuration file, which are shared by every target.
+#[derive(Default)]
+struct Definitions {
+    modes: BTreeMap<String, BuildMode>,
+    templates: Templates,
+}
+
+impl Definitions {
+    /// takes the definitions of `config`, whose relative paths are resolved from `config_dir`.
+    fn new(config: &Config, config_dir: &Path) -> Result<Self, Error> {
+        Ok(Self {
+            modes: config.modes.iter().map(|(name, mode)| (name.clone(), mode.build_mode(name))).collect(),
+            templates: config.templates.load(config_dir)?,
+        })
+    }
+
+    /// reads the definitions of `CONFIG_FILE` in the current directory. nothing is defined if there is no such file.
+    fn load_default() -> Result<Self, Error> {
+        let path = Path::new(CONFIG_FILE);
+        if !path.is_file() {
+            return Ok(Self::default())
+        }
+
+        Self::new(&Config::load(path)?, Path::new(""))
+    }
 }
 
 impl Args {
-    fn validate(self) -> Result<Self, String> {
-        if self.input_file.is_dir() {
-            return Err("The input path must point to file".to_string())
+    const fn logger(&self) -> Logger {
+        let level = match (self.quiet, self.verbose) {
+            (true, _) => LevelFilter::Error,
+            (false, 0) => LevelFilter::Info,
+            (false, 1) => LevelFilter::Debug,
+            (false, _) => LevelFilter::Trace,
+        };
+
+        Logger::new(level, self.log_format)
+    }
+
+    /// fills the options which are not given in the command line with the ones of `target`.
+    /// the paths of `target` are relative to `config_dir`.
+    fn with_target(mut self, target: &Target, config_dir: &Path) -> Self {
+        let resolve = |path: &PathBuf| if is_stdio(path) { path.clone() } else { config_dir.join(path) };
+        // an input file in the command line replaces an input directory of the target, and vice versa
+        if self.input_file.is_none() && self.input_dir.is_none() {
+            self.input_file = target.input.as_ref().map(resolve);
+            self.input_dir = target.input_dir.as_ref().map(resolve);
+        }
+        if self.output_file.is_none() && self.output_dir.is_none() {
+            self.output_file = target.output.as_ref().map(resolve);
+            self.output_dir = target.output_dir.as_ref().map(resolve);
+        }
+        if self.build_mode.is_none() {
+            self.build_mode.clone_from(&target.mode);
+        }
+        if self.exclude.is_empty() {
+            self.exclude.clone_from(&target.exclude);
+        }
+        // the definitions in the command line come later, so that they win
+        self.define = target.variables.clone().into_iter().chain(self.define).collect();
+        if self.processors.is_none() {
+            self.processors.clone_from(&target.processors);
+        }
+        if self.disable.is_empty() {
+            self.disable.clone_from(&target.disable);
+        }
+
+        self
+    }
+
+    fn validate(self, definitions: &Definitions) -> Result<Self, Error> {
+        self.build_mode(definitions)?;
+
+        if let Some(input_file) = self.input_file.as_ref().filter(|input_file| !is_stdio(input_file)) {
+            if input_file.is_dir() {
+                return Err(ErrorKind::InvalidArgument("The input path must point to file".to_string()).into())
+            }
+
+            if !input_file.exists() {
+                return Err(ErrorKind::MissingFile { path: input_file.clone() }.into())
+            }
+        }
+
+        if self.output_file.as_ref().is_some_and(|output_file| !is_stdio(output_file) && output_file.is_dir()) {
+            return Err(ErrorKind::InvalidArgument("The output path must point to file".to_string()).into())
+        }
+
+        let reads_stdin = self.input_file.as_ref().is_some_and(|input_file| is_stdio(input_file));
+        if self.base_dir.is_some() && !reads_stdin {
+            return Err(ErrorKind::InvalidArgument("--base-dir is only for reading the standard input".to_string()).into())
+        }
+
+        if self.diff && self.output_file.as_ref().is_some_and(|output_file| is_stdio(output_file)) {
+            return Err(ErrorKind::InvalidArgument("The standard output can not be compared".to_string()).into())
+        }
+
+        if self.watch && reads_stdin {
+            return Err(ErrorKind::InvalidArgument("The standard input can not be watched".to_string()).into())
+        }
+
+        if self.input_dir.as_ref().is_some_and(|input_dir| !input_dir.is_dir()) {
+            return Err(ErrorKind::InvalidArgument("The input directory must point to directory".to_string()).into())
+        }
+
+        if self.output_dir.as_ref().is_some_and(|output_dir| output_dir.exists() && !output_dir.is_dir()) {
+            return Err(ErrorKind::InvalidArgument("The output directory must point to directory".to_string()).into())
         }
 
-        if !self.input_file.exists() {
-            return Err("The input file must be exist".to_string())
+        if let Some(invalid) = self.exclude.iter().find(|pattern| Pattern::new(pattern).is_err()) {
+            return Err(ErrorKind::InvalidArgument(format!("invalid glob pattern `{invalid}`")).into())
         }
 
-        if self.output_file.is_dir() {
-            return Err("The output path must point to file".to_string())
+        if let Some(unknown) = self.processors.iter().flatten().chain(&self.disable).find(|name| !Pipeline::BUILTIN_NAMES.contains(&name.as_str())) {
+            return Err(ErrorKind::InvalidArgument(format!(
+                "unknown preprocessor `{unknown}`: available preprocessors are {available}",
+                available = Pipeline::BUILTIN_NAMES.iter().join(", "),
+            )).into())
         }
 
         Ok(self)
     }
+
+    fn variables(&self) -> BTreeMap<String, String> {
+        self.define.iter().cloned().collect()
+    }
+
+    /// looks up the build mode by the name, from the modes in the configuration file and the built-in ones.
+    fn build_mode(&self, definitions: &Definitions) -> Result<BuildMode, Error> {
+        let name = self.build_mode.as_deref().ok_or_else(|| {
+            ErrorKind::InvalidArgument("Specify --build-mode, or `mode` of the target in the configuration file".to_string())
+        })?;
+
+        BuildMode::lookup(name, &definitions.modes)
+    }
+
+    fn pipeline(&self) -> Pipeline {
+        let mut pipeline = self.processors.as_ref().map_or_else(Pipeline::builtin, |names| {
+            let mut pipeline = Pipeline::new();
+            pipeline.extend(names.iter().filter_map(|name| Pipeline::builtin_processor(name)));
+            pipeline
+        });
+        for name in &self.disable {
+            pipeline.disable(name);
+        }
+
+        pipeline
+    }
+
+    /// files to preprocess or copy. the outputs may be omitted with `--check`, which writes nothing.
+    fn entries(&self) -> Result<Vec<TreeEntry>, Error> {
+        let output_file = self.output_file.as_ref().or_else(|| self.input_file.as_ref().filter(|_| self.check));
+        let output_dir = self.output_dir.as_ref().or_else(|| self.input_dir.as_ref().filter(|_| self.check));
+        match (&self.input_file, output_file, &self.input_dir, output_dir) {
+            (Some(input_file), Some(output_file), _, _) => Ok(vec![TreeEntry {
+                source: input_file.clone(),
+                destination: output_file.clone(),
+                kind: EntryKind::Document,
+            }]),
+            (_, _, Some(input_dir), Some(output_dir)) => {
+                let exclude = self.exclude.iter().filter_map(|pattern| Pattern::new(pattern).ok()).collect::<Vec<_>>();
+                walk_tree(input_dir, output_dir, &exclude)
+            }
+            _ => Err(ErrorKind::InvalidArgument("Specify either --input-file and --output-file, or --input-dir and --output-dir".to_string()).into()),
+        }
+    }
+}
+
+/// what was done by building the entries, which is reported at the end.
+#[derive(Default)]
+struct Summary {
+    files: usize,
+    includes: usize,
+    bytes: u64,
+}
+
+impl AddAssign for Summary {
+    fn add_assign(&mut self, rhs: Self) {
+        self.files += rhs.files;
+        self.includes += rhs.includes;
+        self.bytes += rhs.bytes;
+    }
+}
+
+impl Summary {
+    fn report(&self) {
+        log::info!(
+            "processed {files} files, expanded {includes} includes, wrote {bytes} bytes",
+            files = self.files,
+            includes = self.includes,
+            bytes = self.bytes,
+        );
+    }
+}
+
+/// parses `name=value` of `--define`.
+fn parse_definition(definition: &str) -> Result<(String, String), String> {
+    match definition.split_once('=') {
+        Some((name, value)) if !name.is_empty() => Ok((name.to_string(), value.to_string())),
+        _ => Err(format!("expected NAME=VALUE, but got `{definition}`")),
+    }
+}
+
+fn is_stdio(path: &Path) -> bool {
+    path.as_os_str() == STDIO
+}
+
+/// writes `content` to `path`, creating the parent directories. `-` means the standard output.
+fn write_output(path: &Path, content: &str) -> Result<(), Error> {
+    let write_error = |e| ErrorKind::Io { path: path.to_path_buf(), source: e };
+    if is_stdio(path) {
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(content.as_bytes()).map_err(write_error)?;
+        stdout.flush().map_err(write_error)?;
+        return Ok(())
+    }
+
+    if let Some(parent) = path.parent().filter(|parent| !parent.as_os_str().is_empty()) {
+        std::fs::create_dir_all(parent).map_err(write_error)?;
+    }
+    let mut output = BufWriter::new(File::options().write(true).create(true).truncate(true).open(path).map_err(write_error)?);
+    output.write_all(content.as_bytes()).map_err(write_error)?;
+    output.flush().map_err(write_error)?;
+
+    Ok(())
+}
+
+/// reads the Markdown file of `entry`, and returns the path from which the paths in it are resolved with the content.
+fn read_input(entry: &TreeEntry, args: &Args) -> Result<(PathBuf, String), Error> {
+    if is_stdio(&entry.source) {
+        let mut input_content = String::new();
+        std::io::stdin().read_to_string(&mut input_content).map_err(|e| ErrorKind::from_io(&entry.source, e))?;
+        // the paths are resolved from the directory of the input file, so a file in the base directory is pretended.
+        Ok((args.base_dir.clone().unwrap_or_default().join("<stdin>"), input_content))
+    } else {
+        let input_content = std::fs::read_to_string(&entry.source).map_err(|e| ErrorKind::from_io(&entry.source, e))?;
+        Ok((entry.source.clone(), input_content))
+    }
+}
+
+/// preprocesses the Markdown file of `entry` in memory, and returns the output with the number of included files.
+/// the files which the output depends on are stored into `dependencies`, even if it fails.
+fn render(entry: &TreeEntry, args: &Args, definitions: &Definitions, pipeline: &Pipeline, dependencies: &mut Vec<PathBuf>) -> Result<(String, usize), Error> {
+    let (input_file, input_content) = read_input(entry, args).inspect_err(|_| {
+        // so that the output is rebuilt when the input is fixed, instead of on every change
+        if !is_stdio(&entry.source) {
+            dependencies.push(entry.source.clone());
+        }
+    })?;
+    let mode = args.build_mode(definitions)?;
+    let build_context = BuildContext::new(&mode, &input_file, pipeline).with_variables(args.variables()).with_modes(definitions.modes.values().cloned()).with_templates(definitions.templates.clone());
+    let output_content = process(input_content, &build_context);
+    // the standard input is not a file to depend on
+    dependencies.extend(build_context.dependencies().into_iter().filter(|dependency| !is_stdio(&entry.source) || *dependency != input_file));
+
+    Ok((output_content?, build_context.includes()))
+}
+
+/// preprocesses the Markdown file, or copies the other file.
+/// the files which the output depends on are stored into `dependencies`, even if it fails.
+fn build(entry: &TreeEntry, args: &Args, definitions: &Definitions, pipeline: &Pipeline, dependencies: &mut Vec<PathBuf>) -> Result<Summary, Error> {
+    dependencies.clear();
+    match entry.kind {
+        EntryKind::Document => {
+            let (output_content, includes) = render(entry, args, definitions, pipeline, dependencies)?;
+            write_output(&entry.destination, &output_content)?;
+
+            Ok(Summary { files: 1, includes, bytes: output_content.len() as u64 })
+        }
+        EntryKind::Asset => {
+            dependencies.push(entry.source.clone());
+            if let Some(parent) = entry.destination.parent() {
+                std::fs::create_dir_all(parent).map_err(|e| ErrorKind::from_io(parent, e))?;
+            }
+            let bytes = std::fs::copy(&entry.source, &entry.destination).map_err(|e| ErrorKind::from_io(&entry.source, e))?;
+
+            Ok(Summary { files: 1, includes: 0, bytes })
+        }
+    }
+}
+
+/// checks the tags in the Markdown file of `entry` as [`build`] does, and returns every problem found.
+fn check(entry: &TreeEntry, args: &Args, definitions: &Definitions, pipeline: &Pipeline) -> Result<Vec<Error>, Error> {
+    let (input_file, input_content) = read_input(entry, args)?;
+    let mode = args.build_mode(definitions)?;
+    let build_context = BuildContext::new(&mode, &input_file, pipeline).with_variables(args.variables()).with_modes(definitions.modes.values().cloned()).with_templates(definitions.templates.clone()).checking();
+    let result = process(input_content, &build_context);
+    let mut problems = build_context.take_problems();
+    problems.extend(result.err());
+
+    Ok(problems)
+}
+
+/// compares the output of `entry` with the existing file, and prints the difference to stdout.
+/// returns whether the existing file is up to date. a missing file is compared as an empty file.
+fn verify(entry: &TreeEntry, args: &Args, definitions: &Definitions, pipeline: &Pipeline) -> Result<bool, Error> {
+    let destination = entry.destination.display();
+    let existing = match std::fs::read(&entry.destination) {
+        Ok(existing) => existing,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => vec![],
+        Err(e) => return Err(ErrorKind::from_io(&entry.destination, e).into()),
+    };
+    match entry.kind {
+        EntryKind::Document => {
+            let (output_content, _) = render(entry, args, definitions, pipeline, &mut vec![])?;
+            let existing = String::from_utf8(existing).map_err(|_| ErrorKind::InvalidUtf8 { path: entry.destination.clone() })?;
+            if existing == output_content {
+                return Ok(true)
+            }
+
+            let diff = TextDiff::from_lines(&existing, &output_content);
+            print!("{}", diff.unified_diff().header(&destination.to_string(), &format!("{destination} (generated)")));
+            Ok(false)
+        }
+        EntryKind::Asset => {
+            let source = std::fs::read(&entry.source).map_err(|e| ErrorKind::from_io(&entry.source, e))?;
+            if existing == source {
+                return Ok(true)
+            }
+
+            println!("Binary files {destination} and {source} differ", source = entry.source.display());
+            Ok(false)
+        }
+    }
 }
 
-#[derive(EnumString, Copy, Clone, Eq, PartialEq, Debug)]
-#[strum(serialize_all = "camelCase")]
-enum BuildMode {
-    Dynamic,
-    Static,
-    Spoiler
-}
-
-struct BuildContext<'ctx> {
-    mode: BuildMode,
-    input_file: &'ctx Path,
-}
-
-trait PreProcessor {
-    fn transform(&self, build_context: &BuildContext<'_>, content: String) -> String;
-}
-
-/**
-* insert inter-link or file content directly
-* tag syntax: {{link or include|&lt;relative path of markdown from root document&gt;}}
-*/
-struct LinkOrInclude;
-
-impl PreProcessor for LinkOrInclude {
-    fn transform(&self, build_context: &BuildContext<'_>, input_content: String) -> String {
-        let pattern = Regex::from_str(r#"\{\{link or include\|./((?:\w+/)+)(\w+\.md)\}\}"#).unwrap();
-        pattern.replace_all(input_content.as_str(), |captures: &Captures| {
-            let file_path = captures.index(1);
-            let file_name = captures.index(2);
-            println!("including: {file_path}/{file_name}");
-            let full_file_path = format!("{file_path}{file_name}");
-            match build_context.mode {
-                BuildMode::Dynamic => format!("This section is migrated. Please see [{file_name}](./{full_file_path})"),
-                BuildMode::Spoiler => {
-                    let mut cloned_path = build_context.input_file.to_path_buf();
-                    cloned_path.pop();
-                    let target_path = cloned_path.join(file_path).join(file_name);
-                    let mut pasting_text = String::from("<details><summary>");
-                    pasting_text.push_str("content of ");
-                    pasting_text.push_str(target_path.to_str().unwrap_or("included file"));
-                    pasting_text.push_str("</summary>\n\n");
-
-                    println!("{target_path}", target_path = &target_path.to_str().unwrap());
-                    let target_path = target_path.canonicalize().unwrap();
-                    println!("{path}", path = &target_path.to_str().unwrap());
-                    let mut fd = BufReader::new(File::open(target_path).expect("Inclusion target file open error"));
-                    let mut buf = String::new();
-                    fd.read_to_string(&mut buf).unwrap();
-
-                    let including_text = {
-                        let include_pat = Regex::from_str(r#"<!-- START -->\n?((.|\n)*)<!-- END -->"#).unwrap();
-                        let ret = include_pat.captures_iter(buf.as_str()).map(|a| {
-                            let to_include = a.index(1).to_string();
-                            // lower header level by one
-                            let header_pat = Regex::from_str("(?m)^(#{1,5})(.*)$").unwrap();
-                            let to_include = header_pat.replace_all(to_include.as_str(), |cap: &Captures| {
-                                format!("#{header}{headline_text}", header = cap.index(1), headline_text = cap.index(2))
-                            }).to_string();
-
-                            to_include
-                        }).join("");
-
-                        ret
-                    };
-
-                    pasting_text.push_str(&including_text);
-                    pasting_text.push_str("\n</details>");
-
-                    pasting_text
+/// escapes `path` for a rule of Makefile, which is also understood by ninja.
+fn escape_for_make(path: &Path) -> String {
+    let mut escaped = String::new();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            ' ' | '#' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            '$' => escaped.push_str("$$"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
+
+/// writes the rules of Makefile which tell that each output depends on the files read to build it.
+fn write_dep_file(path: &Path, built: &[(TreeEntry, Vec<PathBuf>)]) -> Result<(), Error> {
+    let rules = built.iter()
+        .map(|(entry, dependencies)| format!(
+            "{target}: {dependencies}\n",
+            target = escape_for_make(&entry.destination),
+            dependencies = dependencies.iter().map(|dependency| escape_for_make(dependency)).join(" "),
+        ))
+        .join("");
+
+    write_output(path, &rules)
+}
+
+/// rebuilds the outputs whose dependencies change, forever.
+/// errors are reported and do not stop watching, because they are likely fixed by the next change.
+fn watch(args: &Args, definitions: &Definitions, pipeline: &Pipeline) -> ! {
+    let mut watcher = Watcher::new();
+    let mut built: Vec<(TreeEntry, Vec<PathBuf>)> = vec![];
+    let mut changed = vec![];
+    // the error in listing the entries, which is reported only when it changes
+    let mut entries_error = None;
+    loop {
+        // files may appear in or disappear from the input directory
+        match args.entries() {
+            Ok(entries) => {
+                entries_error = None;
+                built.retain(|(built_entry, _)| entries.contains(built_entry));
+                for entry in entries {
+                    if !built.iter().any(|(built_entry, _)| *built_entry == entry) {
+                        built.push((entry, vec![]));
+                    }
                 }
-                BuildMode::Static => {
-                    let mut cloned_path = build_context.input_file.to_path_buf();
-                    cloned_path.pop();
-                    let target_path = cloned_path.join(file_path).join(file_name);
-                    println!("{target_path}", target_path = &target_path.to_str().unwrap());
-                    let target_path = target_path.canonicalize().unwrap();
-                    println!("{path}", path = &target_path.to_str().unwrap());
-                    let mut fd = BufReader::new(File::open(target_path).expect("Inclusion target file open error"));
-                    let mut buf = String::new();
-                    fd.read_to_string(&mut buf).unwrap();
-
-                    let including_text = {
-                        let include_pat = Regex::from_str(r#"<!-- START -->\n?((.|\n)*)<!-- END -->"#).unwrap();
-                        let ret = include_pat.captures_iter(buf.as_str()).map(|a| {
-                            let to_include = a.index(1).to_string();
-                            // lower header level by one
-                            let header_pat = Regex::from_str("(?m)^(#{1,5})(.*)$").unwrap();
-                            let to_include = header_pat.replace_all(to_include.as_str(), |cap: &Captures| {
-                                format!("#{header}{headline_text}", header = cap.index(1), headline_text = cap.index(2))
-                            }).to_string();
-
-                            to_include
-                        }).join("");
-
-                        ret
-                    };
-
-                    including_text
+            }
+            Err(e) => {
+                let message = e.to_string();
+                if entries_error.as_ref() != Some(&message) {
+                    log::error!("{message}");
+                    entries_error = Some(message);
                 }
             }
-        }).to_string()
-    }
-}
-
-/**
- * insert arbitrary file content directly
- * tag syntax: {{include|&lt;relative path from root document&gt;}}
- */
-struct AlwaysInclude;
-
-impl PreProcessor for AlwaysInclude {
-    fn transform(&self, build_context: &BuildContext<'_>, content: String) -> String {
-        let pattern = Regex::from_str(r#"\{\{include\|./((?:\w+/)+)([\w.]+)\}\}"#).unwrap();
-        pattern.replace_all(content.as_str(), |captures: &Captures| {
-            let file_path = captures.index(1);
-            let file_name = captures.index(2);
-            println!("including: {file_path}/{file_name}");
-            let mut cloned_path = build_context.input_file.to_path_buf();
-            cloned_path.pop();
-            let target_path = cloned_path.join(file_path).join(file_name);
-            println!("{target_path}", target_path = &target_path.to_str().unwrap());
-            let target_path = target_path.canonicalize().unwrap();
-            println!("{path}", path = &target_path.to_str().unwrap());
-            let mut fd = BufReader::new(File::open(target_path).expect("Inclusion target file open error"));
-            let mut including_text = String::new();
-            fd.read_to_string(&mut including_text).unwrap();
-
-            including_text
-        }).to_string()
-    }
-}
-
-fn main() {
-    let mut args: Args = Args::parse().validate().unwrap();
-    println!("{args:?}", args = &args);
-    let mut input = BufReader::new(File::open(&args.input_file).expect("failed to open input file"));
-    let input_content = {
-        let mut buf = String::new();
-        input.read_to_string(&mut buf).unwrap();
-        buf
-    };
+        }
 
-    let build_context = BuildContext {
-        mode: args.build_mode,
-        input_file: &mut args.input_file,
+        let mut rebuilt = false;
+        let mut summary = Summary::default();
+        for (entry, dependencies) in &mut built {
+            if dependencies.is_empty() || dependencies.iter().any(|dependency| changed.contains(dependency)) {
+                log::info!("building: {path}", path = entry.source.display());
+                match build(entry, args, definitions, pipeline, dependencies) {
+                    Ok(built) => summary += built,
+                    Err(e) => log::error!("{e}"),
+                }
+                watcher.watch(dependencies.iter().cloned());
+                rebuilt = true;
+            }
+        }
+
+        if rebuilt {
+            summary.report();
+        }
+
+        if let Some(dep_file) = args.dep_file.as_ref().filter(|_| rebuilt) {
+            if let Err(e) = write_dep_file(dep_file, &built) {
+                log::error!("{e}");
+            }
+        }
+
+        std::thread::sleep(Duration::from_millis(args.poll_interval));
+        changed = watcher.changed();
+    }
+}
+
+/// builds the targets described in `config`, or the one named `target`.
+fn build_config(config: &Path, target: Option<&str>, args: &Args) -> Result<(), Error> {
+    let config_dir = config.parent().unwrap_or_else(|| Path::new(""));
+    let loaded = Config::load(config)?;
+    let definitions = Definitions::new(&loaded, config_dir)?;
+    let targets = &loaded.targets;
+    let selected = match target {
+        Some(name) => {
+            let target = targets.get(name).ok_or_else(|| ErrorKind::InvalidArgument(format!(
+                "unknown target `{name}`: available targets are {available}",
+                available = targets.keys().join(", "),
+            )))?;
+            vec![(name, target)]
+        }
+        None => targets.iter().map(|(name, target)| (name.as_str(), target)).collect(),
     };
 
-    let input_content = LinkOrInclude.transform(&build_context, input_content);
-    let input_content = AlwaysInclude.transform(&build_context, input_content);
+    if selected.is_empty() {
+        return Err(ErrorKind::InvalidArgument(format!("{config} has no target", config = config.display())).into())
+    }
+
+    if selected.len() > 1 && (args.watch || args.dep_file.is_some()) {
+        return Err(ErrorKind::InvalidArgument("Specify --target to use --watch or --dep-file".to_string()).into())
+    }
+
+    for (name, target) in selected {
+        log::info!("target: {name}");
+        build_target(&args.clone().with_target(target, config_dir).validate(&definitions)?, &definitions)?;
+    }
+
+    Ok(())
+}
+
+/// builds the documents specified by `args`.
+fn build_target(args: &Args, definitions: &Definitions) -> Result<(), Error> {
+    log::trace!("{args:?}");
+    let pipeline = args.pipeline();
+
+    if args.watch {
+        watch(args, definitions, &pipeline)
+    }
+
+    if args.check {
+        let mut problems = vec![];
+        for entry in args.entries()?.iter().filter(|entry| entry.kind == EntryKind::Document) {
+            problems.extend(check(entry, args, definitions, &pipeline)?);
+        }
+        // a file included from several documents is reported once, in the order of the files and the lines
+        problems.sort_by(|a, b| a.location().cmp(&b.location()));
+        let problems = problems.into_iter().unique_by(ToString::to_string).collect::<Vec<_>>();
+        for problem in &problems {
+            log::error!("{problem}");
+        }
+
+        return if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(ErrorKind::CheckFailed { problems: problems.len() }.into())
+        }
+    }
+
+    if args.diff {
+        let mut outdated = 0;
+        for entry in args.entries()? {
+            if !verify(&entry, args, definitions, &pipeline)? {
+                outdated += 1;
+            }
+        }
+
+        return if outdated == 0 {
+            Ok(())
+        } else {
+            Err(ErrorKind::OutdatedOutput { files: outdated }.into())
+        }
+    }
+
+    let mut built = vec![];
+    let mut summary = Summary::default();
+    for entry in args.entries()? {
+        let mut dependencies = vec![];
+        summary += build(&entry, args, definitions, &pipeline, &mut dependencies)?;
+        built.push((entry, dependencies));
+    }
 
-    let mut output = BufWriter::new(File::options().write(true).create(true).truncate(true).open(args.output_file).unwrap());
-    output.write_all(input_content.as_bytes()).expect("could not write output to destination");
+    if let Some(dep_file) = &args.dep_file {
+        write_dep_file(dep_file, &built)?;
+    }
+    summary.report();
+
+    Ok(())
+}
+
+fn run() -> Result<(), Error> {
+    let cli = Cli::parse();
+    match cli.command {
+        Some(Command::Build { target, config, args }) => {
+            // the logger is installed only once
+            let _ = args.logger().install();
+            build_config(&config, target.as_deref(), &args)
+        }
+        None => {
+            let _ = cli.args.logger().install();
+            let definitions = Definitions::load_default()?;
+            build_target(&cli.args.validate(&definitions)?, &definitions)
+        }
+    }
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            log::error!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escapes_paths_for_make() {
+        assert_eq!(escape_for_make(Path::new("docs/a.md")), "docs/a.md");
+        assert_eq!(escape_for_make(Path::new("my docs/#1.md")), r"my\ docs/\#1.md");
+        assert_eq!(escape_for_make(Path::new("$HOME/a.md")), "$$HOME/a.md");
+    }
+
+    #[test]
+    fn checks_without_outputs() {
+        let cli = Cli::parse_from(["mdtpp", "--check", "-i", "chk.md", "-m", "static"]);
+        let entries = cli.args.entries().unwrap();
+        assert_eq!(entries, vec![TreeEntry { source: "chk.md".into(), destination: "chk.md".into(), kind: EntryKind::Document }]);
+
+        let cli = Cli::parse_from(["mdtpp", "-i", "chk.md", "-m", "static"]);
+        assert!(cli.args.entries().is_err());
+    }
 }
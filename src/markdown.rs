@@ -0,0 +1,453 @@
+//! minimal understanding of Markdown block structure, which is needed to leave code and comments untouched.
+
+use std::ops::Range;
+use std::path::Path;
+
+/// a line of the text, without the line terminator.
+pub struct Line<'a> {
+    pub text: &'a str,
+    /// byte range of the line in the whole text, including the line terminator.
+    pub span: Range<usize>,
+}
+
+/// splits `text` into lines, remembering where each line is.
+pub fn lines(text: &str) -> impl Iterator<Item = Line<'_>> {
+    let mut offset = 0;
+    text.split_inclusive('\n').map(move |raw| {
+        let span = offset..offset + raw.len();
+        offset = span.end;
+        Line {
+            text: raw.trim_end_matches('\n').trim_end_matches('\r'),
+            span,
+        }
+    })
+}
+
+/// whether `path` is a Markdown file.
+#[must_use]
+pub fn is_markdown(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("md") || extension.eq_ignore_ascii_case("markdown"))
+}
+
+/// fence of the fenced code block which `line` opens: the fence character and its length.
+pub fn opening_fence(line: &str) -> Option<(char, usize)> {
+    let trimmed = strip_indent(line, 3)?;
+    let fence_char = trimmed.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let length = trimmed.chars().take_while(|c| *c == fence_char).count();
+    let info = &trimmed[length..];
+    (length >= 3 && !(fence_char == '`' && info.contains('`'))).then_some((fence_char, length))
+}
+
+/// whether `line` closes the fenced code block opened by `fence`.
+pub fn is_closing_fence(line: &str, (fence_char, length): (char, usize)) -> bool {
+    strip_indent(line, 3).is_some_and(|trimmed| {
+        let run = trimmed.chars().take_while(|c| *c == fence_char).count();
+        run >= length && trimmed[run..].trim().is_empty()
+    })
+}
+
+/// whether `line` is a line of an indented code block, if it is not a continuation of a paragraph.
+pub fn is_indented(line: &str) -> bool {
+    !line.trim().is_empty() && (line.starts_with('\t') || line.starts_with("    "))
+}
+
+fn is_list_item(line: &str) -> bool {
+    let trimmed = line.trim_start();
+    let digits = trimmed.chars().take_while(char::is_ascii_digit).count();
+    let marker_end = if digits > 0 && trimmed[digits..].starts_with(['.', ')']) {
+        digits + 1
+    } else if trimmed.starts_with(['-', '*', '+']) {
+        1
+    } else {
+        return false
+    };
+
+    trimmed[marker_end..].is_empty() || trimmed[marker_end..].starts_with([' ', '\t'])
+}
+
+/// removes up to `max` spaces of indentation. fails if `line` is indented more than that.
+fn strip_indent(line: &str, max: usize) -> Option<&str> {
+    let indent = line.chars().take_while(|c| *c == ' ').count();
+    (indent <= max && !line.starts_with('\t')).then(|| &line[indent..])
+}
+
+/// byte ranges of code blocks, fenced or indented, in `text`.
+pub fn code_blocks(text: &str) -> Vec<Range<usize>> {
+    let mut blocks = vec![];
+    let mut fence: Option<((char, usize), usize)> = None;
+    let mut indented: Option<Range<usize>> = None;
+    let mut previous_blank = true;
+    let mut in_list = false;
+
+    for line in lines(text) {
+        if let Some((opened, start)) = fence {
+            if is_closing_fence(line.text, opened) {
+                blocks.push(start..line.span.end);
+                fence = None;
+            }
+            continue
+        }
+
+        if line.text.trim().is_empty() {
+            previous_blank = true;
+            continue
+        }
+
+        if is_indented(line.text) && (indented.is_some() || (previous_blank && !in_list)) {
+            indented = Some(indented.map_or_else(|| line.span.clone(), |block| block.start..line.span.end));
+            continue
+        }
+
+        blocks.extend(indented.take());
+        previous_blank = false;
+
+        if let Some(opened) = opening_fence(line.text) {
+            fence = Some((opened, line.span.start));
+            continue
+        }
+
+        if !is_indented(line.text) {
+            in_list = is_list_item(line.text);
+        }
+    }
+
+    blocks.extend(indented);
+    if let Some((_, start)) = fence {
+        blocks.push(start..text.len());
+    }
+
+    blocks
+}
+
+/// byte ranges of `text` which must be kept verbatim: code blocks, code spans and HTML comments.
+pub fn verbatim_ranges(text: &str) -> Vec<Range<usize>> {
+    let blocks = code_blocks(text);
+    let mut ranges = vec![];
+    let mut gap_start = 0;
+    for block in blocks {
+        inline_verbatim_ranges(text, gap_start..block.start, &mut ranges);
+        gap_start = block.end;
+        ranges.push(block);
+    }
+    inline_verbatim_ranges(text, gap_start..text.len(), &mut ranges);
+
+    ranges
+}
+
+/// finds code spans and HTML comments in `gap`, which contains no code block.
+fn inline_verbatim_ranges(text: &str, gap: Range<usize>, ranges: &mut Vec<Range<usize>>) {
+    let gap_text = &text[gap.clone()];
+    let mut position = 0;
+    while position < gap_text.len() {
+        let rest = &gap_text[position..];
+        if let Some(escaped) = rest.strip_prefix('\\') {
+            position += 1 + escaped.chars().next().map_or(0, char::len_utf8);
+        } else if rest.starts_with("<!--") {
+            let end = rest.find("-->").map_or(gap_text.len(), |close| position + close + 3);
+            ranges.push(gap.start + position..gap.start + end);
+            position = end;
+        } else if rest.starts_with('`') {
+            let length = rest.chars().take_while(|c| *c == '`').count();
+            match closing_backticks(&rest[length..], length) {
+                Some(close) => {
+                    let end = position + length + close + length;
+                    ranges.push(gap.start + position..gap.start + end);
+                    position = end;
+                }
+                None => position += length,
+            }
+        } else {
+            position += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+}
+
+/// finds the run of exactly `length` backticks which closes a code span, before the end of the paragraph.
+fn closing_backticks(text: &str, length: usize) -> Option<usize> {
+    let mut position = 0;
+    while position < text.len() {
+        let rest = &text[position..];
+        if rest.starts_with('`') {
+            let run = rest.chars().take_while(|c| *c == '`').count();
+            if run == length {
+                return Some(position)
+            }
+            position += run;
+        } else if rest.starts_with('\n') && text[position + 1..].lines().next().is_some_and(|line| line.trim().is_empty()) {
+            return None
+        } else {
+            position += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+
+    None
+}
+
+/// the deepest heading level Markdown has.
+pub const MAX_HEADING_LEVEL: usize = 6;
+
+pub struct Heading {
+    /// 1 to 6
+    pub level: usize,
+    /// the content of the heading, without `#`s or the underline.
+    pub text: String,
+    /// byte range of the heading in the whole text, including the underline of the Setext heading
+    /// and the last line terminator.
+    pub span: Range<usize>,
+    /// byte range of the leading `#`s of ATX heading. this is `None` for Setext heading.
+    pub marker: Option<Range<usize>>,
+}
+
+/// level and content of the ATX heading `line`.
+fn atx_heading(line: &str) -> Option<(usize, Range<usize>)> {
+    let trimmed = strip_indent(line, 3)?;
+    let indent = line.len() - trimmed.len();
+    let level = trimmed.chars().take_while(|c| *c == '#').count();
+    let after = &trimmed[level..];
+    ((1..=MAX_HEADING_LEVEL).contains(&level) && (after.is_empty() || after.starts_with([' ', '\t'])))
+        .then_some((level, indent..indent + level))
+}
+
+/// level of the Setext heading which `line` underlines.
+fn setext_underline(line: &str) -> Option<usize> {
+    let trimmed = strip_indent(line, 3)?.trim_end();
+    let underline_char = trimmed.chars().next()?;
+    let level = match underline_char {
+        '=' => 1,
+        '-' => 2,
+        _ => return None,
+    };
+
+    trimmed.chars().all(|c| c == underline_char).then_some(level)
+}
+
+/// finds every heading in `text`, except for ones in code blocks.
+pub fn headings(text: &str) -> Vec<Heading> {
+    let code_blocks = code_blocks(text);
+    let mut headings = vec![];
+    // lines of the paragraph which may be turned into a Setext heading
+    let mut paragraph: Vec<Line<'_>> = vec![];
+
+    for line in lines(text) {
+        if code_blocks.iter().any(|block| block.contains(&line.span.start)) || line.text.trim().is_empty() {
+            paragraph.clear();
+            continue
+        }
+
+        if let Some((level, marker)) = atx_heading(line.text) {
+            let content = &line.text[marker.end..];
+            let content = content.trim().trim_end_matches('#').trim_end();
+            headings.push(Heading {
+                level,
+                text: content.to_string(),
+                marker: Some(line.span.start + marker.start..line.span.start + marker.end),
+                span: line.span,
+            });
+            paragraph.clear();
+            continue
+        }
+
+        if let (Some(level), Some(first)) = (setext_underline(line.text), paragraph.first()) {
+            headings.push(Heading {
+                level,
+                text: paragraph.iter().map(|line| line.text.trim()).collect::<Vec<_>>().join(" "),
+                span: first.span.start..line.span.end,
+                marker: None,
+            });
+            paragraph.clear();
+            continue
+        }
+
+        if is_list_item(line.text) || line.text.trim_start().starts_with('<') || opening_fence(line.text).is_some() {
+            paragraph.clear();
+        } else {
+            paragraph.push(line);
+        }
+    }
+
+    headings
+}
+
+/// level of the last heading which precedes `offset` in `text`.
+pub fn heading_level_before(text: &str, offset: usize) -> Option<usize> {
+    headings(text).into_iter()
+        .take_while(|heading| heading.span.end <= offset)
+        .last()
+        .map(|heading| heading.level)
+}
+
+/// changes the level of every heading in `text` by `shift`. Setext headings are rewritten as ATX headings.
+/// the levels are clamped into 1 to 6. the content of clamped headings is returned with the shifted text.
+pub fn shift_headings(text: &str, shift: isize) -> (String, Vec<String>) {
+    if shift == 0 {
+        return (text.to_string(), vec![])
+    }
+
+    let mut shifted = String::with_capacity(text.len());
+    let mut clamped = vec![];
+    let mut last = 0;
+    for heading in headings(text) {
+        let unclamped = heading.level.saturating_add_signed(shift);
+        let level = unclamped.clamp(1, MAX_HEADING_LEVEL);
+        if heading.level.checked_add_signed(shift) != Some(level) {
+            clamped.push(heading.text.clone());
+        }
+
+        let marker = "#".repeat(level);
+        if let Some(marker_span) = heading.marker {
+            shifted.push_str(&text[last..marker_span.start]);
+            shifted.push_str(&marker);
+            last = marker_span.end;
+        } else {
+            shifted.push_str(&text[last..heading.span.start]);
+            shifted.push_str(&marker);
+            shifted.push(' ');
+            shifted.push_str(&heading.text);
+            if text[..heading.span.end].ends_with('\n') {
+                shifted.push('\n');
+            }
+            last = heading.span.end;
+        }
+    }
+    shifted.push_str(&text[last..]);
+
+    (shifted, clamped)
+}
+
+/// the shift which places the shallowest heading of `included` just under the heading of level `site_level`.
+pub fn default_heading_shift(site_level: usize, included: &str) -> isize {
+    headings(included).iter()
+        .map(|heading| heading.level)
+        .min()
+        .map_or(0, |shallowest| site_level.cast_signed() + 1 - shallowest.cast_signed())
+}
+
+/// GitHub-style anchor of the heading whose content is `text`.
+pub fn slug(text: &str) -> String {
+    text.trim()
+        .to_lowercase()
+        .chars()
+        .filter(|c| c.is_alphanumeric() || matches!(c, ' ' | '-' | '_'))
+        .map(|c| if c == ' ' { '-' } else { c })
+        .collect()
+}
+
+/// byte range of the section under the heading whose anchor is `anchor`: from the heading to the next heading
+/// of the same or shallower level. duplicated anchors are distinguished by `-1`, `-2` and so on, as GitHub does.
+pub fn heading_section(text: &str, anchor: &str) -> Option<Range<usize>> {
+    let headings = headings(text);
+    let mut seen = std::collections::HashMap::<String, usize>::new();
+    let position = headings.iter().position(|heading| {
+        let slug = slug(&heading.text);
+        let count = seen.entry(slug.clone()).or_insert(0);
+        let unique = if *count == 0 { slug } else { format!("{slug}-{count}") };
+        *count += 1;
+        unique == anchor
+    })?;
+
+    let heading = &headings[position];
+    let end = headings[position + 1..].iter()
+        .find(|next| next.level <= heading.level)
+        .map_or(text.len(), |next| next.span.start);
+
+    Some(heading.span.start..end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn verbatim(text: &str) -> Vec<&str> {
+        verbatim_ranges(text).into_iter().map(|range| &text[range]).collect()
+    }
+
+    #[test]
+    fn finds_fenced_code_blocks() {
+        assert_eq!(code_blocks("a\n```rs\n{{x}}\n```\nb\n"), vec![2..18]);
+        // a shorter fence does not close the block
+        assert_eq!(code_blocks("~~~~\nx\n~~~\n~~~~~\n"), vec![0..17]);
+        // an unclosed block lasts until the end
+        assert_eq!(code_blocks("```\nx\n"), vec![0..6]);
+        // backticks in the info string do not open a block
+        assert!(code_blocks("``` a`b\nx\n").is_empty());
+    }
+
+    #[test]
+    fn finds_indented_code_blocks() {
+        assert_eq!(code_blocks("    code\n"), vec![0..9]);
+        assert_eq!(code_blocks("a\n\n    code\n    more\nb\n"), vec![3..21]);
+        // continuation of a paragraph or a list item
+        assert!(code_blocks("a\n    not code\n").is_empty());
+        assert!(code_blocks("- item\n\n    continued\n").is_empty());
+    }
+
+    #[test]
+    fn finds_code_spans() {
+        assert_eq!(verbatim("a `{{x}}` b"), vec!["`{{x}}`"]);
+        assert_eq!(verbatim("``a ` b`` c"), vec!["``a ` b``"]);
+        assert!(verbatim("a `b").is_empty());
+        assert!(verbatim("\\`a`").is_empty());
+        // a code span does not continue over a blank line
+        assert!(verbatim("`a\n\nb`").is_empty());
+    }
+
+    #[test]
+    fn finds_html_comments() {
+        assert_eq!(verbatim("x <!-- {{y}} --> z"), vec!["<!-- {{y}} -->"]);
+        assert_eq!(verbatim("x <!-- a"), vec!["<!-- a"]);
+        assert_eq!(verbatim("`a`\n```\nb\n```\n<!-- c -->"), vec!["`a`", "```\nb\n```\n", "<!-- c -->"]);
+    }
+
+    #[test]
+    fn finds_headings() {
+        let headings = headings("# A #\nB\n===\n\n  ## C\n```\n# not\n```\na\n\n---\n#no\n");
+        let found = headings.iter().map(|heading| (heading.level, heading.text.as_str())).collect::<Vec<_>>();
+        assert_eq!(found, vec![(1, "A"), (1, "B"), (2, "C")]);
+    }
+
+    #[test]
+    fn shifts_headings() {
+        assert_eq!(shift_headings("# A\n## B\n", 1), ("## A\n### B\n".to_string(), vec![]));
+        assert_eq!(shift_headings("### A\n#### B\n", -2), ("# A\n## B\n".to_string(), vec![]));
+        assert_eq!(shift_headings("# A\n", 0), ("# A\n".to_string(), vec![]));
+        assert_eq!(shift_headings("```\n# not\n```\n# yes\n", 1).0, "```\n# not\n```\n## yes\n");
+    }
+
+    #[test]
+    fn rewrites_setext_headings() {
+        assert_eq!(shift_headings("Title\n=====\n\ntext\n", 1).0, "## Title\n\ntext\n");
+        assert_eq!(shift_headings("Sub\n---", 1).0, "### Sub");
+    }
+
+    #[test]
+    fn clamps_shifted_levels() {
+        assert_eq!(shift_headings("# A\n###### B\n", 1), ("## A\n###### B\n".to_string(), vec!["B".to_string()]));
+        assert_eq!(shift_headings("# A\n## B\n", -1), ("# A\n# B\n".to_string(), vec!["A".to_string()]));
+        assert_eq!(shift_headings("## A\n", -9), ("# A\n".to_string(), vec!["A".to_string()]));
+    }
+
+    #[test]
+    fn computes_default_shift() {
+        assert_eq!(default_heading_shift(2, "# A\n## B\n"), 2);
+        assert_eq!(default_heading_shift(0, "### A\n"), -2);
+        assert_eq!(default_heading_shift(3, "no heading\n"), 0);
+        assert_eq!(heading_level_before("# A\n## B\ntext", 9), Some(2));
+        assert_eq!(heading_level_before("text", 2), None);
+    }
+
+    #[test]
+    fn finds_sections_by_anchor() {
+        let text = "# A\n## B\ntext\n## B\nmore\n# C\n";
+        assert_eq!(heading_section(text, "a"), Some(0..24));
+        assert_eq!(heading_section(text, "b"), Some(4..14));
+        assert_eq!(heading_section(text, "b-1"), Some(14..24));
+        assert_eq!(heading_section(text, "c"), Some(24..28));
+        assert_eq!(heading_section(text, "x"), None);
+    }
+
+    #[test]
+    fn slugs_like_github() {
+        assert_eq!(slug("Hello, World!"), "hello-world");
+        assert_eq!(slug(" A_b c-d "), "a_b-c-d");
+    }
+}
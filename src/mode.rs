@@ -0,0 +1,138 @@
+use std::collections::{BTreeMap, BTreeSet};
+use itertools::Itertools;
+use serde::Deserialize;
+use crate::error::{Error, ErrorKind};
+use crate::processor::{AlwaysInclude, LinkOrInclude};
+
+/// how a tag which includes a file is rendered.
+#[derive(Deserialize, Copy, Clone, Eq, PartialEq, Debug, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum Strategy {
+    /// a link to the file.
+    Link,
+    /// the content of the file.
+    #[default]
+    Inline,
+    /// the content of the file in a collapsed `<details>` element.
+    DetailsCollapsed,
+    /// nothing.
+    Omit,
+}
+
+/// how the tags are rendered. the built-in modes are `dynamic`, `static` and `spoiler`, and more modes can be defined
+/// in the configuration file.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct BuildMode {
+    name: String,
+    /// the strategies by the names of tags. the tags not listed are rendered inline.
+    strategies: BTreeMap<String, Strategy>,
+    /// extra flags, which can be tested by `{{if mode.<flag>}}`.
+    flags: BTreeSet<String>,
+}
+
+impl BuildMode {
+    pub const BUILTIN_NAMES: [&'static str; 3] = ["dynamic", "static", "spoiler"];
+
+    /// the names of the tags whose rendering is decided by the build mode.
+    pub const TAGS: [&'static str; 2] = [LinkOrInclude::TAG, AlwaysInclude::NAME];
+
+    #[must_use]
+    pub fn new(name: impl Into<String>, strategies: BTreeMap<String, Strategy>, flags: BTreeSet<String>) -> Self {
+        Self { name: name.into(), strategies, flags }
+    }
+
+    /// looks up the built-in mode named `name`.
+    /// `dynamic` links the files of `{{link or include}}`, `static` includes them, and `spoiler` includes them
+    /// in collapsed `<details>`. every mode includes the files of `{{include}}`.
+    #[must_use]
+    pub fn builtin(name: &str) -> Option<Self> {
+        let strategy = match name {
+            "dynamic" => Some(Strategy::Link),
+            "static" => None,
+            "spoiler" => Some(Strategy::DetailsCollapsed),
+            _ => return None,
+        };
+        let strategies = strategy.map(|strategy| (LinkOrInclude::TAG.to_string(), strategy)).into_iter().collect();
+
+        Some(Self::new(name, strategies, BTreeSet::new()))
+    }
+
+    /// looks up the mode named `name` from `defined`, and then from the built-in ones.
+    ///
+    /// # Errors
+    /// fails if there is no such mode.
+    pub fn lookup(name: &str, defined: &BTreeMap<String, Self>) -> Result<Self, Error> {
+        defined.get(name).cloned()
+            .or_else(|| Self::builtin(name))
+            .ok_or_else(|| ErrorKind::UnknownMode { name: name.to_string(), available: Self::names(defined.keys()) }.into())
+    }
+
+    /// the names of the built-in modes followed by `defined`, without duplicates.
+    #[must_use]
+    pub fn names<'a>(defined: impl IntoIterator<Item = &'a String>) -> Vec<String> {
+        Self::BUILTIN_NAMES.into_iter()
+            .chain(defined.into_iter().map(String::as_str))
+            .unique()
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// the tag named `name`, which may also be the name of the preprocessor expanding it, such as `link-or-include`.
+    #[must_use]
+    pub fn tag_named(name: &str) -> Option<&'static str> {
+        match name {
+            LinkOrInclude::NAME => Some(LinkOrInclude::TAG),
+            name => Self::TAGS.into_iter().find(|tag| *tag == name),
+        }
+    }
+
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// how the tag named `tag` is rendered.
+    #[must_use]
+    pub fn strategy(&self, tag: &str) -> Strategy {
+        self.strategies.get(tag).copied().unwrap_or_default()
+    }
+
+    #[must_use]
+    pub fn flag(&self, flag: &str) -> bool {
+        self.flags.contains(flag)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_tags_by_tags_or_processors() {
+        assert_eq!(BuildMode::tag_named("link or include"), Some("link or include"));
+        assert_eq!(BuildMode::tag_named("link-or-include"), Some("link or include"));
+        assert_eq!(BuildMode::tag_named("include"), Some("include"));
+        assert_eq!(BuildMode::tag_named("if"), None);
+        assert_eq!(BuildMode::tag_named("includ"), None);
+    }
+
+    #[test]
+    fn looks_up_defined_modes_before_builtin_ones() {
+        let print = BuildMode::new("print", BTreeMap::new(), BTreeSet::from(["paper".to_string()]));
+        let static_link = BuildMode::new("static", BTreeMap::from([(AlwaysInclude::NAME.to_string(), Strategy::Link)]), BTreeSet::new());
+        let defined = BTreeMap::from([("print".to_string(), print.clone()), ("static".to_string(), static_link.clone())]);
+        assert_eq!(BuildMode::lookup("print", &defined).unwrap(), print);
+        assert_eq!(BuildMode::lookup("static", &defined).unwrap(), static_link);
+        assert_eq!(BuildMode::lookup("dynamic", &defined).unwrap().strategy(LinkOrInclude::TAG), Strategy::Link);
+    }
+
+    #[test]
+    fn lists_available_modes_for_unknown_ones() {
+        let defined = BTreeMap::from([("print".to_string(), BuildMode::builtin("static").unwrap())]);
+        let e = BuildMode::lookup("prnt", &defined).unwrap_err();
+        assert_eq!(e.to_string(), ErrorKind::UnknownMode {
+            name: "prnt".to_string(),
+            available: vec!["dynamic".to_string(), "static".to_string(), "spoiler".to_string(), "print".to_string()],
+        }.to_string());
+    }
+}
@@ -0,0 +1,105 @@
+use crate::context::BuildContext;
+use crate::error::{Error, ErrorKind};
+use crate::processor::{AlwaysInclude, Conditional, LinkOrInclude, PreProcessor, Variable};
+use crate::tag::{parse, Node};
+
+/// ordered list of preprocessors which are run over a document.
+#[derive(Default)]
+pub struct Pipeline {
+    processors: Vec<Box<dyn PreProcessor>>,
+}
+
+impl Pipeline {
+    /// creates a pipeline with no preprocessor.
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// creates a pipeline which runs every built-in preprocessor in the default order.
+    #[must_use]
+    pub fn builtin() -> Self {
+        let mut pipeline = Self::new();
+        for name in Self::BUILTIN_NAMES {
+            pipeline.processors.extend(Self::builtin_processor(name));
+        }
+
+        pipeline
+    }
+
+    /// names of the built-in preprocessors, in the default order.
+    /// conditional blocks are evaluated first, so that the tags in the removed parts are never expanded.
+    /// variables are substituted before files are included, so a value can be a part of the path to include.
+    pub const BUILTIN_NAMES: [&'static str; 4] = [Conditional::NAME, Variable::NAME, LinkOrInclude::NAME, AlwaysInclude::NAME];
+
+    /// looks up the built-in preprocessor named `name`.
+    #[must_use]
+    pub fn builtin_processor(name: &str) -> Option<Box<dyn PreProcessor>> {
+        match name {
+            Conditional::NAME => Some(Box::new(Conditional)),
+            Variable::NAME => Some(Box::new(Variable)),
+            LinkOrInclude::NAME => Some(Box::new(LinkOrInclude)),
+            AlwaysInclude::NAME => Some(Box::new(AlwaysInclude)),
+            _ => None,
+        }
+    }
+
+    /// appends `processor` to the end of this pipeline.
+    pub fn register(&mut self, processor: impl PreProcessor + 'static) -> &mut Self {
+        self.processors.push(Box::new(processor));
+        self
+    }
+
+    /// removes every preprocessor named `name`. returns whether any of them was removed.
+    pub fn disable(&mut self, name: &str) -> bool {
+        let before = self.processors.len();
+        self.processors.retain(|processor| processor.name() != name);
+        self.processors.len() != before
+    }
+
+    /// names of the registered preprocessors, in the running order.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.processors.iter().map(|processor| processor.name())
+    }
+
+    /// runs every registered preprocessor over `content` in order.
+    ///
+    /// # Errors
+    /// fails if any of the preprocessors fails.
+    pub fn run(&self, build_context: &BuildContext<'_>, content: String) -> Result<String, Error> {
+        build_context.start_source(&content);
+        self.report_unknown_tags(build_context, &content);
+        self.processors.iter().try_fold(content, |content, processor| processor.transform(build_context, content))
+    }
+
+    /// whether `tag` is expanded by any of the registered or built-in preprocessors.
+    /// the tags of the built-in preprocessors which are not registered are left in the output on purpose.
+    fn knows(&self, tag: &str) -> bool {
+        self.processors.iter().any(|processor| processor.handles(tag))
+            || Self::BUILTIN_NAMES.into_iter().filter_map(Self::builtin_processor).any(|processor| processor.handles(tag))
+    }
+
+    /// reports the tags in `content` which no preprocessor knows, because they are likely misspelled.
+    /// they are problems while checking, and warnings otherwise.
+    fn report_unknown_tags(&self, build_context: &BuildContext<'_>, content: &str) {
+        for node in parse(content) {
+            let Node::Tag(tag) = node else { continue };
+            if self.knows(&tag.name) {
+                continue
+            }
+
+            let e = Error::from(ErrorKind::UnknownTag { name: tag.name }).at(|| build_context.locate(content, tag.span.start));
+            if build_context.is_checking() {
+                build_context.report(e);
+            } else {
+                log::warn!("{e}");
+            }
+        }
+    }
+}
+
+impl Extend<Box<dyn PreProcessor>> for Pipeline {
+    fn extend<T: IntoIterator<Item = Box<dyn PreProcessor>>>(&mut self, iter: T) {
+        self.processors.extend(iter);
+    }
+}
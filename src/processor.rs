@@ -0,0 +1,304 @@
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use crate::context::BuildContext;
+use crate::error::{Error, ErrorKind};
+use crate::mode::Strategy;
+use crate::markdown::{default_heading_shift, heading_level_before, heading_section, headings, shift_headings, MAX_HEADING_LEVEL};
+use crate::region::regions;
+use crate::tag::Tag;
+use crate::template::{fill, needs_content};
+
+mod always_include;
+mod conditional;
+mod link_or_include;
+mod variable;
+
+pub use always_include::AlwaysInclude;
+pub use conditional::Conditional;
+pub use link_or_include::LinkOrInclude;
+pub use variable::Variable;
+
+pub trait PreProcessor {
+    /// the name which identifies this preprocessor in a [`Pipeline`](crate::Pipeline).
+    fn name(&self) -> &str;
+
+    /// whether this preprocessor expands the tags named `tag`. the tags are named after the preprocessor by default.
+    fn handles(&self, tag: &str) -> bool {
+        tag == self.name()
+    }
+
+    /// expands the tags handled by this preprocessor in `content`, which is read from `build_context.input_file()`.
+    ///
+    /// # Errors
+    /// fails if any of the tags can not be expanded.
+    fn transform(&self, build_context: &BuildContext<'_>, content: String) -> Result<String, Error>;
+}
+
+/// the path given as the first argument of `tag`.
+///
+/// # Errors
+/// fails if `tag` has no argument.
+pub fn path_argument<'t>(tag: &'t Tag, expected: &'static str) -> Result<&'t str, Error> {
+    tag.argument(0)
+        .filter(|path| !path.is_empty())
+        .ok_or_else(|| ErrorKind::BadTagSyntax { tag: tag.name.clone(), expected }.into())
+}
+
+/// splits the path argument like `./docs/setup.md#install` into the path and the name of the region or heading anchor.
+#[must_use]
+pub fn split_fragment(path: &str) -> (&str, Option<&str>) {
+    match path.rsplit_once('#') {
+        Some((path, fragment)) if !fragment.is_empty() => (path, Some(fragment)),
+        Some((path, _)) => (path, None),
+        None => (path, None),
+    }
+}
+
+/// resolves `relative_path` written in the file of `build_context`, from the directory of the file.
+#[must_use]
+pub fn resolve_path(build_context: &BuildContext<'_>, relative_path: &str) -> PathBuf {
+    let mut cloned_path = build_context.input_file().to_path_buf();
+    cloned_path.pop();
+    cloned_path.join(relative_path)
+}
+
+/// reads the whole content of `path` as UTF-8 text.
+pub fn read_to_string(path: &Path) -> Result<String, Error> {
+    let mut fd = BufReader::new(File::open(path).map_err(|e| ErrorKind::from_io(path, e))?);
+    let mut buf = String::new();
+    fd.read_to_string(&mut buf).map_err(|e| ErrorKind::from_io(path, e))?;
+
+    Ok(buf)
+}
+
+/// resolves `target_path` and reads the whole content of it. the file is recorded as a dependency of the output.
+pub fn read_included_file(build_context: &BuildContext<'_>, target_path: &Path) -> Result<(PathBuf, String), Error> {
+    let canonical_path = target_path.canonicalize().map_err(|e| {
+        build_context.record_dependency(target_path);
+        ErrorKind::from_io(target_path, e)
+    })?;
+    build_context.record_dependency(&canonical_path);
+    log::debug!("including: {path}", path = canonical_path.display());
+    log::trace!("{path} is resolved to {canonical_path}", path = target_path.display(), canonical_path = canonical_path.display());
+    let buf = read_to_string(&canonical_path)?;
+    build_context.record_include();
+    if build_context.is_checking() {
+        // the markers are checked even if no region is selected, because they will break the file some day.
+        if let Err(e) = regions(&canonical_path, &buf) {
+            build_context.report(e);
+        }
+    }
+
+    Ok((canonical_path, buf))
+}
+
+/// byte ranges of the region named `name` in `buf`, which is read from `target_path`.
+/// if there is no such region, the section under the heading whose anchor is `name` is selected instead.
+/// if `name` is `None`, every region marked with `<!-- START -->` and `<!-- END -->` is selected.
+///
+/// # Errors
+/// fails if the markers are broken, or nothing is selected.
+pub fn find_regions(target_path: &Path, buf: &str, name: Option<&str>) -> Result<Vec<Range<usize>>, Error> {
+    let mut selected = regions(target_path, buf)?
+        .into_iter()
+        .filter(|region| region.name.as_deref() == name)
+        .map(|region| region.content)
+        .collect::<Vec<_>>();
+    if selected.is_empty() {
+        selected.extend(name.and_then(|anchor| heading_section(buf, anchor)));
+    }
+    if selected.is_empty() {
+        return Err(ErrorKind::MissingRegion { path: target_path.to_path_buf(), name: name.map(str::to_string) }.into())
+    }
+
+    Ok(selected)
+}
+
+/// expands tags in `text`, which is taken from `buf[offset..]` of the included file `target_path`.
+///
+/// # Errors
+/// fails if `target_path` is already being expanded, or any of the tags in `text` can not be expanded.
+pub fn expand_included(build_context: &BuildContext<'_>, target_path: &Path, buf: &str, offset: usize, text: String) -> Result<String, Error> {
+    let child_context = build_context.enter(target_path)?;
+    child_context.read_front_matter(buf);
+    let expanded = child_context.pipeline().run(&child_context, text);
+    for problem in child_context.take_problems() {
+        build_context.report(problem.after(target_path, &buf[..offset]));
+    }
+
+    expanded.map_err(|e| e.after(target_path, &buf[..offset]))
+}
+
+/// expands tags in the region named `name` of `buf`, which is read from `target_path`.
+/// see [`find_regions`] for how the region is selected. if more than one region is selected, they are concatenated.
+pub fn expand_region(build_context: &BuildContext<'_>, target_path: &Path, buf: &str, name: Option<&str>) -> Result<String, Error> {
+    let included = find_regions(target_path, buf, name)?.into_iter()
+        .map(|range| expand_included(build_context, target_path, buf, range.start, buf[range].to_string()))
+        .collect::<Result<Vec<_>, Error>>()?;
+
+    Ok(included.join(""))
+}
+
+/// the `shift=N` option of `tag`, which changes the heading levels of the included text by `N`.
+///
+/// # Errors
+/// fails if the option is not an integer.
+pub fn shift_option(tag: &Tag) -> Result<Option<isize>, Error> {
+    tag.option("shift")
+        .map(|shift| shift.parse().map_err(|_| ErrorKind::BadTagSyntax { tag: tag.name.clone(), expected: "shift=<integer>" }.into()))
+        .transpose()
+}
+
+/// changes the heading levels of `included`, which replaces `tag` in `content`, by `shift`.
+/// if `shift` is `None`, the shallowest heading of `included` is placed just under the heading preceding `tag`.
+/// headings which can not be shifted as requested are warned.
+#[must_use]
+pub fn shift_included_headings(build_context: &BuildContext<'_>, content: &str, tag: &Tag, included: &str, shift: Option<isize>) -> String {
+    let shift = shift.unwrap_or_else(|| {
+        let site_level = heading_level_before(content, tag.span.start).unwrap_or(0);
+        default_heading_shift(site_level, included)
+    });
+    let (shifted, clamped) = shift_headings(included, shift);
+    for heading in clamped {
+        log::warn!(
+            "{location}: the level of heading `{heading}` is clamped into 1 to {MAX_HEADING_LEVEL}",
+            location = build_context.locate(content, tag.span.start),
+        );
+    }
+
+    shifted
+}
+
+/// renders the file `target_path` included by a tag as `path`, by `strategy`.
+/// `link` and `details-collapsed` are rendered with the templates of `build_context`.
+/// `include` expands the content of the file, given with its resolved path. the file is not read if the strategy does
+/// not need the content, unless checking.
+///
+/// # Errors
+/// fails if the file can not be read, or `include` fails.
+pub fn render_included(
+    build_context: &BuildContext<'_>,
+    strategy: Strategy,
+    path: &str,
+    target_path: &Path,
+    include: impl FnOnce(&Path, &str) -> Result<String, Error>,
+) -> Result<String, Error> {
+    let read_and_include = || {
+        let (target_path, buf) = read_included_file(build_context, target_path)?;
+        include(&target_path, &buf).map(|content| (buf, content))
+    };
+    let templates = build_context.templates();
+    let template = match strategy {
+        Strategy::Link => &templates.link,
+        Strategy::DetailsCollapsed => &templates.details,
+        Strategy::Inline => return read_and_include().map(|(_, content)| content),
+        Strategy::Omit if build_context.is_checking() => return read_and_include().map(|_| String::new()),
+        Strategy::Omit => return Ok(String::new()),
+    };
+    let (buf, content) = if needs_content(template) || build_context.is_checking() {
+        let (buf, content) = read_and_include()?;
+        (Some(buf), content)
+    } else {
+        (None, String::new())
+    };
+
+    let (file_path, fragment) = split_fragment(path);
+    // a region is not an anchor in the rendered file, so the link points to the file itself
+    let link_path = match fragment {
+        Some(fragment) if !is_heading_anchor(build_context, target_path, buf.as_deref(), fragment) => file_path,
+        _ => path,
+    };
+    let file_name = Path::new(file_path).file_name().map_or_else(|| file_path.into(), |name| name.to_string_lossy());
+    let full_path = target_path.to_str().ok_or_else(|| ErrorKind::NonUtf8Path { path: target_path.to_path_buf() })?;
+    let title = headings(&content).into_iter().next().map_or_else(|| file_name.to_string(), |heading| heading.text);
+    Ok(fill(template, &[
+        ("file_name", &file_name),
+        ("path", path),
+        ("link", &link_destination(link_path)),
+        ("full_path", full_path),
+        ("title", &title),
+        ("content", &content),
+    ]))
+}
+
+/// whether `fragment` names a heading anchor of `target_path`, rather than a region.
+/// `buf` is the content of the file if it has already been read. otherwise the file is read here, and recorded as a
+/// dependency of the output if it exists, because the link changes with its headings.
+fn is_heading_anchor(build_context: &BuildContext<'_>, target_path: &Path, buf: Option<&str>, fragment: &str) -> bool {
+    let read;
+    let buf = if let Some(buf) = buf {
+        buf
+    } else {
+        let Ok(canonical_path) = target_path.canonicalize() else { return false };
+        build_context.record_dependency(&canonical_path);
+        let Ok(buf) = read_to_string(&canonical_path) else { return false };
+        read = buf;
+        &read
+    };
+    let is_region = regions(target_path, buf).is_ok_and(|regions| regions.iter().any(|region| region.name.as_deref() == Some(fragment)));
+
+    !is_region && heading_section(buf, fragment).is_some()
+}
+
+/// formats `path` as a destination of Markdown link. it is enclosed in `<>` if it contains spaces or parentheses.
+fn link_destination(path: &str) -> String {
+    let path = if path.starts_with("./") || path.starts_with("../") || Path::new(path).is_absolute() {
+        path.to_string()
+    } else {
+        format!("./{path}")
+    };
+
+    if path.contains(|c: char| c.is_whitespace() || c == '(' || c == ')') {
+        format!("<{path}>")
+    } else {
+        path
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::mode::BuildMode;
+    use crate::pipeline::Pipeline;
+    use crate::testing::{expand, TempDir};
+    use super::*;
+
+    #[test]
+    fn resolves_paths_from_the_directory_of_the_file() {
+        let mode = BuildMode::builtin("static").unwrap();
+        let pipeline = Pipeline::builtin();
+        let context = BuildContext::new(&mode, Path::new("docs/guide/index.md"), &pipeline);
+        assert_eq!(resolve_path(&context, "./a.md"), Path::new("docs/guide/./a.md"));
+        assert_eq!(resolve_path(&context, "../a.md"), Path::new("docs/guide/../a.md"));
+    }
+
+    #[test]
+    fn resolves_nested_includes_from_the_including_file() {
+        let dir = TempDir::new();
+        let input_file = dir.write("index.md", "{{include|./sub/a.md}}\n");
+        dir.write("sub/a.md", "A {{include|./b.md}} {{include|../c.md}}\n");
+        dir.write("sub/b.md", "B");
+        dir.write("b.md", "wrong B");
+        dir.write("c.md", "C");
+        assert_eq!(expand(&input_file, "static").unwrap(), "A B C\n\n");
+    }
+
+    #[test]
+    fn links_to_heading_anchors_but_not_to_regions() {
+        let dir = TempDir::new();
+        let input = "{{link or include|./b.md#sec}}\n{{link or include|./b.md#reg}}\n{{link or include|./missing.md#sec}}\n";
+        let input_file = dir.write("index.md", input);
+        let included_file = dir.write("b.md", "# B\n\n## Sec\n\n<!-- START:reg -->\nr\n<!-- END:reg -->\n");
+        let mode = BuildMode::builtin("dynamic").unwrap();
+        let pipeline = Pipeline::builtin();
+        let context = BuildContext::new(&mode, &input_file, &pipeline);
+        assert_eq!(crate::process(input.to_string(), &context).unwrap(), concat!(
+            "This section is migrated. Please see [b.md](./b.md#sec)\n",
+            "This section is migrated. Please see [b.md](./b.md)\n",
+            "This section is migrated. Please see [missing.md](./missing.md)\n",
+        ));
+        // the missing file is not a dependency, which would break the rules of make.
+        assert_eq!(context.dependencies(), vec![input_file, included_file]);
+    }
+}
@@ -0,0 +1,79 @@
+use crate::context::BuildContext;
+use crate::error::{Error, ErrorKind};
+use crate::front_matter::front_matter;
+use crate::markdown::is_markdown;
+use crate::processor::{expand_included, find_regions, path_argument, render_included, resolve_path, shift_included_headings, shift_option, split_fragment, PreProcessor};
+use crate::snippet::{dedent, fence, language_of, LineSelection};
+use crate::tag::replace_tags;
+
+/**
+ * insert arbitrary file content directly
+ * tag syntax: {{include|&lt;relative path from the including document&gt;[#&lt;region name&gt;]|shift=&lt;N&gt;|lines=&lt;A-B&gt;|from=/&lt;regex&gt;/|to=/&lt;regex&gt;/|fence=&lt;language&gt;}}
+ *
+ * the whole file is included, or only the region marked with `<!-- START:name -->` and `<!-- END:name -->`
+ * if the name of the region is specified.
+ * the front matter of an included Markdown file is left out.
+ * `lines=A-B` selects the lines from `A` to `B` (1-origin, inclusive) of them. `from=/regex/` and `to=/regex/` select
+ * the lines from the first line matching `from` to the next line matching `to`. the selected lines are dedented.
+ *
+ * source code such as `.rs` or `.toml` is enclosed in a fenced code block of the language inferred from its extension,
+ * and tags in it are not expanded. `fence=<language>` specifies the language, and `fence=none` includes it as is.
+ *
+ * the headings of the included text are shifted by `N` levels if `shift` is specified.
+ * the file is included in every built-in mode, but a mode in the configuration file can render it in other ways.
+ */
+pub struct AlwaysInclude;
+
+impl AlwaysInclude {
+    pub const NAME: &'static str = "include";
+}
+
+impl PreProcessor for AlwaysInclude {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn transform(&self, build_context: &BuildContext<'_>, content: String) -> Result<String, Error> {
+        replace_tags(build_context, &content, Self::NAME, |tag| {
+            let relative_path = path_argument(tag, "{{include|<relative path>}}")?;
+            let shift = shift_option(tag)?;
+            let selection = LineSelection::from_tag(tag)?;
+            let (file_path, region) = split_fragment(relative_path);
+            let target_path = resolve_path(build_context, file_path);
+            let strategy = build_context.mode().strategy(Self::NAME);
+            render_included(build_context, strategy, relative_path, &target_path, |target_path, buf| {
+                let mut range = match region {
+                    Some(region) => find_regions(target_path, buf, Some(region))?.swap_remove(0),
+                    // the front matter describes the included file itself, so it is not a part of the content.
+                    None if is_markdown(target_path) => front_matter(buf).map_or(0, |front_matter| front_matter.span.end)..buf.len(),
+                    None => 0..buf.len(),
+                };
+                let mut including_text = buf[range.clone()].to_string();
+                if let Some(selection) = selection {
+                    let selected = selection.select(&including_text).ok_or_else(|| ErrorKind::EmptySelection {
+                        path: target_path.to_path_buf(),
+                        selection: selection.description().to_string(),
+                    })?;
+                    range = range.start + selected.start..range.start + selected.end;
+                    including_text = dedent(&buf[range.clone()]);
+                }
+                let language = match tag.option("fence") {
+                    None | Some("auto") => target_path.extension().and_then(|extension| language_of(&extension.to_string_lossy())),
+                    Some("none") => None,
+                    Some(language) => Some(language),
+                };
+                if let Some(language) = language {
+                    return Ok(fence(&including_text, language))
+                }
+
+                let including_text = expand_included(build_context, target_path, buf, range.start, including_text)?;
+
+                Ok(if shift.is_some() || region.is_some() {
+                    shift_included_headings(build_context, &content, tag, &including_text, shift)
+                } else {
+                    including_text
+                })
+            })
+        })
+    }
+}
@@ -0,0 +1,269 @@
+use std::ops::Range;
+use crate::context::BuildContext;
+use crate::error::{Error, ErrorKind};
+use crate::processor::PreProcessor;
+use crate::source_map::Edit;
+use crate::tag::{broken_tag_error, broken_tags, parse, Node, Tag};
+
+/**
+ * keep or remove a part of the document by the build mode or variables
+ * tag syntax: {{if &lt;condition&gt;}} ... {{else}} ... {{endif}}
+ *
+ * the condition is one of `mode=<build mode>`, `mode.<flag>`, `var.<name> == "<value>"`, `var.<name> != "<value>"`
+ * or `var.<name>`. `mode.<flag>` holds if the build mode has the flag, and `var.<name>` holds if the variable is
+ * defined and neither empty, `false` nor `no`.
+ * blocks can be nested, and `{{else}}` is optional. a tag on its own line is removed with the line.
+ *
+ * this runs before files are included, so the files in the removed part are never read.
+ */
+pub struct Conditional;
+
+impl Conditional {
+    pub const NAME: &'static str = "if";
+}
+
+enum BlockTag<'t> {
+    If(&'t str),
+    Else,
+    EndIf,
+}
+
+struct Block {
+    /// the `{{if}}` tag which opens this block.
+    opening: Tag,
+    holds: bool,
+    in_else: bool,
+}
+
+impl PreProcessor for Conditional {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn handles(&self, tag: &str) -> bool {
+        block_tag(tag).is_some()
+    }
+
+    fn transform(&self, build_context: &BuildContext<'_>, content: String) -> Result<String, Error> {
+        match keep_active_blocks(build_context, &content) {
+            Ok((output, edits)) => {
+                build_context.record_edits(edits);
+                Ok(output)
+            }
+            Err(e) if build_context.is_checking() => {
+                // the blocks are left as is, so that the problems in the rest of the document are found too.
+                build_context.report(e);
+                Ok(content)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// removes the inactive parts of the blocks and the block tags from `content`, and returns the result with the edits.
+fn keep_active_blocks(build_context: &BuildContext<'_>, content: &str) -> Result<(String, Vec<Edit>), Error> {
+    if let Some((start, name)) = broken_tags(content).into_iter().find(|(_, name)| block_tag(name).is_some()) {
+        return Err(broken_tag_error(build_context, content, start, name))
+    }
+
+    let unbalanced = |tag: &Tag| Error::from(ErrorKind::UnbalancedMarker { marker: content[tag.span.clone()].to_string() })
+        .at(|| build_context.locate(content, tag.span.start));
+    let mut blocks: Vec<Block> = vec![];
+    let mut kept = vec![];
+    let mut kept_start = Some(0);
+    for node in parse(content) {
+        let Node::Tag(tag) = node else { continue };
+        let Some(block_tag) = block_tag(&tag.name) else { continue };
+        let span = whole_line(content, tag.span.clone());
+        if let Some(start) = kept_start {
+            kept.push(start..span.start);
+        }
+
+        match block_tag {
+            BlockTag::If(condition) => {
+                let holds = evaluate(build_context, condition)
+                    .map_err(|e| e.at(|| build_context.locate(content, tag.span.start)))?;
+                blocks.push(Block { opening: tag, holds, in_else: false });
+            }
+            BlockTag::Else => match blocks.last_mut() {
+                Some(block) if !block.in_else => block.in_else = true,
+                _ => return Err(unbalanced(&tag)),
+            },
+            BlockTag::EndIf => {
+                blocks.pop().ok_or_else(|| unbalanced(&tag))?;
+            }
+        }
+        let active = blocks.iter().all(|block| block.holds != block.in_else);
+        kept_start = active.then_some(span.end);
+    }
+    if let Some(block) = blocks.first() {
+        return Err(unbalanced(&block.opening))
+    }
+    if let Some(start) = kept_start {
+        kept.push(start..content.len());
+    }
+
+    let mut output = String::with_capacity(content.len());
+    let mut edits = vec![];
+    let mut last = 0;
+    for range in kept {
+        if last < range.start {
+            edits.push(Edit { replaced: last..range.start, length: 0 });
+        }
+        output.push_str(&content[range.clone()]);
+        last = range.end;
+    }
+    if last < content.len() {
+        edits.push(Edit { replaced: last..content.len(), length: 0 });
+    }
+
+    Ok((output, edits))
+}
+
+/// the block tag named `name`.
+fn block_tag(name: &str) -> Option<BlockTag<'_>> {
+    match name {
+        "else" => Some(BlockTag::Else),
+        "endif" => Some(BlockTag::EndIf),
+        "if" => Some(BlockTag::If("")),
+        name => name.strip_prefix("if ").map(BlockTag::If),
+    }
+}
+
+/// extends `span` to the whole line including the line terminator, if nothing but spaces is around it in the line.
+fn whole_line(content: &str, span: Range<usize>) -> Range<usize> {
+    let line_start = content[..span.start].rfind('\n').map_or(0, |newline| newline + 1);
+    let line_end = content[span.end..].find('\n').map_or(content.len(), |newline| span.end + newline + 1);
+    if content[line_start..span.start].trim().is_empty() && content[span.end..line_end].trim().is_empty() {
+        line_start..line_end
+    } else {
+        span
+    }
+}
+
+/// evaluates the condition of `{{if <condition>}}`.
+fn evaluate(build_context: &BuildContext<'_>, condition: &str) -> Result<bool, Error> {
+    const EXPECTED: &str = r#"{{if mode=<build mode>}}, {{if mode.<flag>}} or {{if var.<name> == "<value>"}}"#;
+    let bad_syntax = || Error::from(ErrorKind::BadTagSyntax { tag: Conditional::NAME.to_string(), expected: EXPECTED });
+    let comparison = match condition.find(['=', '!']) {
+        Some(position) => {
+            let rest = &condition[position..];
+            let operator = ["!=", "==", "="].into_iter().find(|operator| rest.starts_with(operator)).ok_or_else(bad_syntax)?;
+            Some((condition[..position].trim(), operator, unquote(rest[operator.len()..].trim())))
+        }
+        None => None,
+    };
+
+    match comparison {
+        Some(("mode", operator, mode)) => {
+            // a misspelled mode would never match silently
+            build_context.lookup_mode(mode)?;
+            Ok((build_context.mode().name() == mode) != (operator == "!="))
+        }
+        Some((left, operator, value)) => {
+            let name = left.strip_prefix("var.").filter(|name| !name.is_empty()).ok_or_else(bad_syntax)?;
+            Ok((build_context.variable(name).as_deref() == Some(value)) != (operator == "!="))
+        }
+        None => match condition.trim().split_once('.') {
+            Some(("mode", flag)) if !flag.is_empty() => Ok(build_context.mode().flag(flag)),
+            Some(("var", name)) if !name.is_empty() => {
+                Ok(build_context.variable(name).is_some_and(|value| !matches!(value.as_str(), "" | "false" | "no")))
+            }
+            _ => Err(bad_syntax()),
+        },
+    }
+}
+
+/// removes the quotes around `value` if any.
+fn unquote(value: &str) -> &str {
+    ['"', '\'']
+        .into_iter()
+        .find_map(|quote| value.strip_prefix(quote).and_then(|value| value.strip_suffix(quote)))
+        .unwrap_or(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+    use std::path::Path;
+    use crate::mode::BuildMode;
+    use crate::pipeline::Pipeline;
+    use super::*;
+
+    fn print_mode() -> BuildMode {
+        BuildMode::new("print", BTreeMap::new(), BTreeSet::from(["paper".to_string()]))
+    }
+
+    /// runs `f` with a context of the mode `mode`, which has the variable `edition=pro`.
+    fn with_context<T>(mode: &str, f: impl FnOnce(&BuildContext<'_>) -> T) -> T {
+        let mode = BuildMode::builtin(mode).unwrap_or_else(print_mode);
+        let pipeline = Pipeline::builtin();
+        let context = BuildContext::new(&mode, Path::new("doc.md"), &pipeline)
+            .with_variables(BTreeMap::from([("edition".to_string(), "pro".to_string())]))
+            .with_modes([print_mode()]);
+        f(&context)
+    }
+
+    fn keep(mode: &str, content: &str) -> Result<String, Error> {
+        with_context(mode, |context| keep_active_blocks(context, content).map(|(output, _)| output))
+    }
+
+    #[test]
+    fn keeps_the_active_branch() {
+        let content = "a\n{{if mode=static}}\nS\n{{else}}\nD\n{{endif}}\nb\n";
+        assert_eq!(keep("static", content).unwrap(), "a\nS\nb\n");
+        assert_eq!(keep("dynamic", content).unwrap(), "a\nD\nb\n");
+        assert_eq!(keep("static", "a {{if mode!=static}}x{{endif}} b").unwrap(), "a  b");
+    }
+
+    #[test]
+    fn nests_blocks() {
+        let content = "{{if mode.paper}}\nP\n{{if var.edition == \"pro\"}}\nPro\n{{else}}\nFree\n{{endif}}\n{{endif}}\n";
+        assert_eq!(keep("print", content).unwrap(), "P\nPro\n");
+        assert_eq!(keep("static", content).unwrap(), "");
+    }
+
+    #[test]
+    fn rejects_unbalanced_blocks() {
+        for content in ["{{endif}}", "{{if mode=static}}\n", "{{else}}", "{{if mode=static}}{{else}}{{else}}{{endif}}"] {
+            let e = keep("static", content).unwrap_err();
+            assert!(matches!(e.kind(), ErrorKind::UnbalancedMarker { .. }), "{content}: {e}");
+        }
+    }
+
+    #[test]
+    fn evaluates_conditions() {
+        with_context("print", |context| {
+            assert!(evaluate(context, "mode=print").unwrap());
+            assert!(evaluate(context, " mode = 'print' ").unwrap());
+            assert!(!evaluate(context, "mode=static").unwrap());
+            assert!(evaluate(context, "mode!=static").unwrap());
+            assert!(evaluate(context, "mode.paper").unwrap());
+            assert!(!evaluate(context, "mode.web").unwrap());
+            assert!(evaluate(context, r#"var.edition == "pro""#).unwrap());
+            assert!(evaluate(context, r#"var.edition != "free""#).unwrap());
+            assert!(evaluate(context, "var.edition").unwrap());
+            assert!(!evaluate(context, "var.missing").unwrap());
+        });
+    }
+
+    #[test]
+    fn rejects_malformed_conditions() {
+        with_context("static", |context| {
+            let e = evaluate(context, "mode=statc").unwrap_err();
+            assert!(matches!(e.kind(), ErrorKind::UnknownMode { .. }), "{e}");
+            for condition in ["", "mode", "mode.", "edition == \"pro\"", "var.a ! b"] {
+                let e = evaluate(context, condition).unwrap_err();
+                assert!(matches!(e.kind(), ErrorKind::BadTagSyntax { .. }), "{condition}: {e}");
+            }
+        });
+    }
+
+    #[test]
+    fn removes_tags_with_their_own_lines() {
+        let content = "a\n  {{endif}}  \nb {{endif}}\n{{endif}}";
+        assert_eq!(whole_line(content, 4..13), 2..16);
+        assert_eq!(whole_line(content, 18..27), 18..27);
+        assert_eq!(whole_line(content, 28..37), 28..37);
+    }
+}
@@ -0,0 +1,89 @@
+use crate::context::BuildContext;
+use crate::error::Error;
+use crate::processor::{expand_region, path_argument, render_included, resolve_path, shift_included_headings, shift_option, split_fragment, PreProcessor};
+use crate::tag::replace_tags;
+
+/**
+* insert inter-link or file content directly
+* tag syntax: {{link or include|&lt;relative path of markdown from the including document&gt;[#&lt;region name&gt;]|shift=&lt;N&gt;|mode=&lt;build mode&gt;}}
+*
+* the region marked with `<!-- START -->` and `<!-- END -->` is included, or the region marked with
+* `<!-- START:name -->` and `<!-- END:name -->` if the name of the region is specified. if there is no such region,
+* the section under the heading whose anchor is the name is included.
+* the headings of the included text are shifted by `N` levels. if `shift` is omitted, they are placed under the
+* heading which precedes the tag.
+*
+* the build mode decides whether the file is linked, included, included in collapsed `<details>`, or omitted.
+* `mode` renders this tag as in the build mode of the name, instead of the current one. the included file itself is
+* expanded in the current build mode.
+*/
+pub struct LinkOrInclude;
+
+impl LinkOrInclude {
+    pub const NAME: &'static str = "link-or-include";
+    pub const TAG: &'static str = "link or include";
+}
+
+impl PreProcessor for LinkOrInclude {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn handles(&self, tag: &str) -> bool {
+        tag == Self::TAG
+    }
+
+    fn transform(&self, build_context: &BuildContext<'_>, input_content: String) -> Result<String, Error> {
+        replace_tags(build_context, &input_content, Self::TAG, |tag| {
+            let full_file_path = path_argument(tag, "{{link or include|<relative path>}}")?;
+            let shift = shift_option(tag)?;
+            let (file_path, region) = split_fragment(full_file_path);
+            let target_path = resolve_path(build_context, file_path);
+            let strategy = match tag.option("mode") {
+                Some(mode) => build_context.lookup_mode(mode)?.strategy(Self::TAG),
+                None => build_context.mode().strategy(Self::TAG),
+            };
+            log::debug!("rendering {full_file_path} as {strategy:?}");
+            render_included(build_context, strategy, full_file_path, &target_path, |target_path, buf| {
+                let including_text = expand_region(build_context, target_path, buf, region)?;
+
+                Ok(shift_included_headings(build_context, &input_content, tag, &including_text, shift))
+            })
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, BTreeSet};
+    use crate::error::ErrorKind;
+    use crate::mode::{BuildMode, Strategy};
+    use crate::pipeline::Pipeline;
+    use crate::process;
+    use crate::testing::TempDir;
+    use super::*;
+
+    fn expand(input: &str) -> Result<String, Error> {
+        let dir = TempDir::new();
+        let input_file = dir.write("index.md", input);
+        dir.write("b.md", "<!-- START -->\nB\n<!-- END -->\n");
+        let mode = BuildMode::builtin("dynamic").unwrap();
+        let pipeline = Pipeline::builtin();
+        let omit = BuildMode::new("omit", BTreeMap::from([(LinkOrInclude::TAG.to_string(), Strategy::Omit)]), BTreeSet::new());
+        let context = BuildContext::new(&mode, &input_file, &pipeline).with_modes([omit]);
+        process(input.to_string(), &context)
+    }
+
+    #[test]
+    fn renders_in_the_mode_given_by_the_tag() {
+        assert_eq!(expand("{{link or include|./b.md}}\n").unwrap(), "This section is migrated. Please see [b.md](./b.md)\n");
+        assert_eq!(expand("{{link or include|./b.md|mode=static}}\n").unwrap(), "B\n\n");
+        assert_eq!(expand("a{{link or include|./b.md|mode=omit}}\n").unwrap(), "a\n");
+    }
+
+    #[test]
+    fn rejects_unknown_modes_in_the_tag() {
+        let e = expand("{{link or include|./b.md|mode=statc}}\n").unwrap_err();
+        assert!(matches!(e.kind(), ErrorKind::UnknownMode { .. }), "{e}");
+    }
+}
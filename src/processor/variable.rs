@@ -0,0 +1,71 @@
+use crate::context::BuildContext;
+use crate::error::{Error, ErrorKind};
+use crate::processor::PreProcessor;
+use crate::tag::{escape, replace_tags};
+
+/**
+ * insert the value of a variable
+ * tag syntax: {{var|&lt;name&gt;|default=&lt;value&gt;}}
+ *
+ * variables are given by `--define name=value`, `variables` of the target in the configuration file, or front matter
+ * of the document. `default` is inserted if the variable is not defined.
+ * `{{` in the value is inserted as is, and not expanded as a tag.
+ */
+pub struct Variable;
+
+impl Variable {
+    pub const NAME: &'static str = "var";
+}
+
+impl PreProcessor for Variable {
+    fn name(&self) -> &str {
+        Self::NAME
+    }
+
+    fn transform(&self, build_context: &BuildContext<'_>, content: String) -> Result<String, Error> {
+        replace_tags(build_context, &content, Self::NAME, |tag| {
+            let name = tag.argument(0)
+                .filter(|name| !name.is_empty())
+                .ok_or_else(|| ErrorKind::BadTagSyntax { tag: tag.name.clone(), expected: "{{var|<name>}}" })?;
+
+            build_context.variable(name)
+                .or_else(|| tag.option("default").map(str::to_string))
+                .map(|value| escape(&value))
+                .ok_or_else(|| ErrorKind::UndefinedVariable { name: name.to_string() }.into())
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+    use std::path::Path;
+    use crate::mode::BuildMode;
+    use crate::pipeline::Pipeline;
+    use crate::process;
+    use crate::testing::TempDir;
+    use super::*;
+
+    fn expand(input_file: &Path, input: &str, name: &str, value: &str) -> Result<String, Error> {
+        let mode = BuildMode::builtin("static").unwrap();
+        let pipeline = Pipeline::builtin();
+        let context = BuildContext::new(&mode, input_file, &pipeline)
+            .with_variables(BTreeMap::from([(name.to_string(), value.to_string())]));
+        process(input.to_string(), &context)
+    }
+
+    #[test]
+    fn inserts_tags_in_values_as_text() {
+        let output = expand(Path::new("doc.md"), "{{var|a}}\n", "a", "{{include|/etc/hostname}}").unwrap();
+        assert_eq!(output, "{{include|/etc/hostname}}\n");
+    }
+
+    #[test]
+    fn expands_variables_in_tags() {
+        let dir = TempDir::new();
+        let input = "{{include|./{{var|lang}}/b.md}}\n";
+        let input_file = dir.write("doc.md", input);
+        dir.write("en/b.md", "English\n");
+        assert_eq!(expand(&input_file, input, "lang", "en").unwrap(), "English\n\n");
+    }
+}
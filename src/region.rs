@@ -0,0 +1,138 @@
+//! regions of a file marked with `<!-- START -->` and `<!-- END -->`, or `<!-- START:name -->` and `<!-- END:name -->`.
+
+use std::ops::Range;
+use std::path::Path;
+use std::str::FromStr;
+use regex::Regex;
+use crate::error::{Error, ErrorKind, Location};
+use crate::markdown::verbatim_ranges;
+
+pub struct Region {
+    /// `None` for the region marked with `<!-- START -->` and `<!-- END -->`.
+    pub name: Option<String>,
+    /// byte range of the text between the markers. the line terminator just after the start marker is excluded.
+    pub content: Range<usize>,
+}
+
+struct Marker<'a> {
+    start: bool,
+    name: Option<&'a str>,
+    span: Range<usize>,
+}
+
+/// finds every marker in `text` except for ones in code, in the order of appearance.
+fn markers(text: &str) -> Vec<Marker<'_>> {
+    let pattern = Regex::from_str(r"<!--\s*(START|END)(?::\s*([\w.-]+))?\s*-->").unwrap();
+    let verbatim = verbatim_ranges(text);
+    pattern.captures_iter(text)
+        .filter_map(|captures| {
+            let whole = captures.get(0)?;
+            // markers are HTML comments, so they are verbatim by themselves, but must not be a part of code.
+            let in_code = verbatim.iter().any(|range| range.start < whole.start() && whole.start() < range.end);
+            (!in_code).then(|| Marker {
+                start: &captures[1] == "START",
+                name: captures.get(2).map(|name| name.as_str()),
+                span: whole.range(),
+            })
+        })
+        .collect()
+}
+
+/// finds every region in `text`, which is read from `path`.
+///
+/// # Errors
+/// fails if a marker has no counterpart, or two regions have the same name.
+pub fn regions(path: &Path, text: &str) -> Result<Vec<Region>, Error> {
+    let marker_error = |kind: ErrorKind, marker: &Marker<'_>| Error::from(kind).at(|| Location::from_offset(path, text, marker.span.start));
+    let mut open: Vec<Marker<'_>> = vec![];
+    let mut regions: Vec<Region> = vec![];
+
+    for marker in markers(text) {
+        if marker.start {
+            if open.iter().any(|opened| opened.name == marker.name) {
+                return Err(marker_error(ErrorKind::UnbalancedMarker { marker: marker_text(&marker) }, &marker))
+            }
+            open.push(marker);
+            continue
+        }
+
+        let Some(position) = open.iter().rposition(|opened| opened.name == marker.name) else {
+            return Err(marker_error(ErrorKind::UnbalancedMarker { marker: marker_text(&marker) }, &marker))
+        };
+        let opened = open.remove(position);
+        if let Some(name) = opened.name {
+            if regions.iter().any(|region| region.name.as_deref() == Some(name)) {
+                return Err(marker_error(ErrorKind::DuplicateRegion { name: name.to_string() }, &opened))
+            }
+        }
+
+        let content_start = opened.span.end + usize::from(text[opened.span.end..].starts_with('\n'));
+        regions.push(Region {
+            name: opened.name.map(str::to_string),
+            content: content_start.min(marker.span.start)..marker.span.start,
+        });
+    }
+
+    if let Some(unclosed) = open.first() {
+        return Err(marker_error(ErrorKind::UnbalancedMarker { marker: marker_text(unclosed) }, unclosed))
+    }
+
+    regions.sort_by_key(|region| region.content.start);
+    Ok(regions)
+}
+
+fn marker_text(marker: &Marker<'_>) -> String {
+    let kind = if marker.start { "START" } else { "END" };
+    marker.name.map_or_else(|| format!("<!-- {kind} -->"), |name| format!("<!-- {kind}:{name} -->"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn contents(text: &str) -> Vec<(Option<String>, &str)> {
+        regions(Path::new("a.md"), text).unwrap().into_iter()
+            .map(|region| (region.name, &text[region.content]))
+            .collect()
+    }
+
+    /// the message and the line of the error.
+    fn error(text: &str) -> (String, usize) {
+        let Err(e) = regions(Path::new("a.md"), text) else { panic!("{text} has no error") };
+        (e.kind().to_string(), e.location().map_or(0, |location| location.line))
+    }
+
+    #[test]
+    fn finds_regions() {
+        let text = "x\n<!-- START -->\nbody\n<!-- END -->\n<!--START:a-->A<!-- END: a -->\n";
+        assert_eq!(contents(text), vec![(None, "body\n"), (Some("a".to_string()), "A")]);
+    }
+
+    #[test]
+    fn finds_nested_regions() {
+        let text = "<!-- START:a -->\n<!-- START:b -->\nx\n<!-- END:b -->\n<!-- END:a -->";
+        assert_eq!(contents(text), vec![
+            (Some("a".to_string()), "<!-- START:b -->\nx\n<!-- END:b -->\n"),
+            (Some("b".to_string()), "x\n"),
+        ]);
+    }
+
+    #[test]
+    fn ignores_markers_in_code() {
+        assert!(contents("```\n<!-- START -->\n```\n`<!-- END -->`\n").is_empty());
+    }
+
+    #[test]
+    fn rejects_duplicate_regions() {
+        let text = "<!-- START:a -->\n1\n<!-- END:a -->\n<!-- START:a -->\n2\n<!-- END:a -->\n";
+        assert_eq!(error(text), ("region `a` is defined more than once".to_string(), 4));
+    }
+
+    #[test]
+    fn rejects_unbalanced_markers() {
+        assert_eq!(error("<!-- END -->"), ("`<!-- END -->` has no counterpart".to_string(), 1));
+        assert_eq!(error("a\n<!-- START:x -->\n"), ("`<!-- START:x -->` has no counterpart".to_string(), 2));
+        assert_eq!(error("<!-- START -->\n<!-- START -->\n<!-- END -->"), ("`<!-- START -->` has no counterpart".to_string(), 2));
+        assert_eq!(error("<!-- START:a -->\n<!-- END:b -->"), ("`<!-- END:b -->` has no counterpart".to_string(), 2));
+    }
+}
@@ -0,0 +1,271 @@
+//! selection of lines from included files, by `lines=A-B`, `from=/regex/` and `to=/regex/` options.
+
+use std::ops::Range;
+use std::str::FromStr;
+use regex::Regex;
+use crate::error::{Error, ErrorKind};
+use crate::markdown::lines;
+use crate::tag::Tag;
+
+/// a line which starts or ends the selection.
+enum Boundary {
+    Pattern(Regex),
+    Text(String),
+}
+
+impl Boundary {
+    /// `/regex/` is a regular expression, and anything else is a plain text to find.
+    fn parse(tag: &Tag, value: &str, expected: &'static str) -> Result<Self, Error> {
+        value.strip_prefix('/').and_then(|value| value.strip_suffix('/')).map_or_else(
+            || Ok(Self::Text(value.to_string())),
+            |pattern| Regex::from_str(pattern)
+                .map(Self::Pattern)
+                .map_err(|_| ErrorKind::BadTagSyntax { tag: tag.name.clone(), expected }.into()),
+        )
+    }
+
+    fn matches(&self, line: &str) -> bool {
+        match self {
+            Self::Pattern(pattern) => pattern.is_match(line),
+            Self::Text(text) => line.contains(text.as_str()),
+        }
+    }
+}
+
+pub struct LineSelection {
+    /// 1-origin, inclusive range of line numbers
+    lines: Option<(Option<usize>, Option<usize>)>,
+    from: Option<Boundary>,
+    to: Option<Boundary>,
+    /// the options as written, to report which selection failed
+    description: String,
+}
+
+impl LineSelection {
+    /// reads `lines`, `from` and `to` options of `tag`. returns `None` if none of them is specified.
+    ///
+    /// # Errors
+    /// fails if any of the options is malformed.
+    pub fn from_tag(tag: &Tag) -> Result<Option<Self>, Error> {
+        let lines = tag.option("lines").map(|lines| parse_line_range(lines).ok_or_else(|| Error::from(ErrorKind::BadTagSyntax {
+            tag: tag.name.clone(),
+            expected: "lines=<first>-<last>",
+        }))).transpose()?;
+        let from = tag.option("from").map(|from| Boundary::parse(tag, from, "from=/<regex>/")).transpose()?;
+        let to = tag.option("to").map(|to| Boundary::parse(tag, to, "to=/<regex>/")).transpose()?;
+        if lines.is_none() && from.is_none() && to.is_none() {
+            return Ok(None)
+        }
+
+        let description = ["lines", "from", "to"].into_iter()
+            .filter_map(|key| tag.option(key).map(|value| format!("{key}={value}")))
+            .collect::<Vec<_>>()
+            .join("|");
+
+        Ok(Some(Self { lines, from, to, description }))
+    }
+
+    /// byte range of the selected lines in `text`. `None` if no line is selected.
+    #[must_use]
+    pub fn select(&self, text: &str) -> Option<Range<usize>> {
+        let lines = lines(text).collect::<Vec<_>>();
+        let (first, last) = self.lines.unwrap_or((None, None));
+        let first = first.unwrap_or(1).checked_sub(1)?;
+        let last = last.unwrap_or(lines.len()).min(lines.len());
+        if first >= last {
+            return None
+        }
+
+        let candidates = &lines[first..last];
+        let start = match &self.from {
+            Some(from) => candidates.iter().position(|line| from.matches(line.text))?,
+            None => 0,
+        };
+        let end = match &self.to {
+            Some(to) => {
+                let search_from = if self.from.is_some() { start + 1 } else { start };
+                search_from + candidates.get(search_from..)?.iter().position(|line| to.matches(line.text))?
+            }
+            None => candidates.len() - 1,
+        };
+
+        Some(candidates[start].span.start..candidates[end].span.end)
+    }
+
+    /// the options which made this selection, like `from=/fn main/|to=/^}/`.
+    #[must_use]
+    pub fn description(&self) -> &str {
+        &self.description
+    }
+}
+
+/// parses `A-B`, `A-`, `-B` or `A`.
+fn parse_line_range(range: &str) -> Option<(Option<usize>, Option<usize>)> {
+    let parse_bound = |bound: &str| -> Option<Option<usize>> {
+        let bound = bound.trim();
+        if bound.is_empty() {
+            Some(None)
+        } else {
+            bound.parse().ok().filter(|line| *line > 0).map(Some)
+        }
+    };
+
+    if let Some((first, last)) = range.split_once('-') {
+        Some((parse_bound(first)?, parse_bound(last)?))
+    } else {
+        let line = parse_bound(range)??;
+        Some((Some(line), Some(line)))
+    }
+}
+
+/// removes the indentation which is common to every non-blank line of `text`.
+#[must_use]
+pub fn dedent(text: &str) -> String {
+    let indent = text.lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| &line[..line.len() - line.trim_start().len()])
+        .reduce(|common, indent| {
+            let shared = common.chars().zip(indent.chars()).take_while(|(a, b)| a == b).map(|(c, _)| c.len_utf8()).sum();
+            &common[..shared]
+        })
+        .unwrap_or("");
+
+    text.split_inclusive('\n')
+        .map(|line| line.strip_prefix(indent).unwrap_or_else(|| line.trim_start_matches([' ', '\t'])))
+        .collect()
+}
+
+/// language of the code block for the file whose extension is `extension`.
+/// `None` for Markdown, HTML, plain text and unknown files, which are included as they are.
+#[must_use]
+pub fn language_of(extension: &str) -> Option<&'static str> {
+    let language = match extension.to_ascii_lowercase().as_str() {
+        "rs" => "rust",
+        "toml" => "toml",
+        "json" => "json",
+        "yml" | "yaml" => "yaml",
+        "sh" | "bash" => "sh",
+        "zsh" => "zsh",
+        "ps1" => "powershell",
+        "py" => "python",
+        "rb" => "ruby",
+        "js" | "mjs" | "cjs" => "javascript",
+        "ts" | "mts" | "cts" => "typescript",
+        "c" | "h" => "c",
+        "cc" | "cpp" | "cxx" | "hpp" => "cpp",
+        "cs" => "csharp",
+        "go" => "go",
+        "java" => "java",
+        "kt" | "kts" => "kotlin",
+        "swift" => "swift",
+        "css" => "css",
+        "xml" => "xml",
+        "sql" => "sql",
+        "diff" | "patch" => "diff",
+        "dockerfile" => "dockerfile",
+        "mk" => "makefile",
+        _ => return None,
+    };
+
+    Some(language)
+}
+
+/// encloses `text` in a fenced code block of `language`.
+/// the fence is longer than any run of backticks in `text`, so that `text` can not close the block.
+#[must_use]
+pub fn fence(text: &str, language: &str) -> String {
+    let longest_run = text.split(|c| c != '`').map(str::len).max().unwrap_or(0);
+    let fence = "`".repeat((longest_run + 1).max(3));
+    let newline = if text.ends_with('\n') || text.is_empty() { "" } else { "\n" };
+
+    format!("{fence}{language}\n{text}{newline}{fence}")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tag::{parse, Node};
+
+    fn tag(text: &str) -> Tag {
+        parse(text).into_iter()
+            .find_map(|node| match node {
+                Node::Tag(tag) => Some(tag),
+                Node::Text(_) => None,
+            })
+            .unwrap()
+    }
+
+    fn select<'a>(options: &str, text: &'a str) -> Option<&'a str> {
+        let selection = LineSelection::from_tag(&tag(&format!("{{{{include|a.rs|{options}}}}}"))).unwrap().unwrap();
+        selection.select(text).map(|range| &text[range])
+    }
+
+    #[test]
+    fn selects_nothing_without_options() {
+        assert!(LineSelection::from_tag(&tag("{{include|a.rs|shift=1}}")).unwrap().is_none());
+    }
+
+    #[test]
+    fn selects_line_ranges() {
+        let text = "a\nb\nc\nd\n";
+        assert_eq!(select("lines=2-3", text), Some("b\nc\n"));
+        assert_eq!(select("lines=2", text), Some("b\n"));
+        assert_eq!(select("lines=-2", text), Some("a\nb\n"));
+        assert_eq!(select("lines=3-", text), Some("c\nd\n"));
+        assert_eq!(select("lines=3-9", text), Some("c\nd\n"));
+        assert_eq!(select("lines=4", "a\nb\nc\nd"), Some("d"));
+        assert_eq!(select("lines=5-9", text), None);
+        assert_eq!(select("lines=3-2", text), None);
+    }
+
+    #[test]
+    fn rejects_malformed_options() {
+        for options in ["lines=0", "lines=a-b", "lines=1-2-3", "lines=", "from=/(/", "to=/[/"] {
+            let tag = tag(&format!("{{{{include|a.rs|{options}}}}}"));
+            assert!(LineSelection::from_tag(&tag).is_err(), "{options}");
+        }
+    }
+
+    #[test]
+    fn selects_lines_by_patterns() {
+        let text = "fn a() {\n    x\n}\nfn b() {\n}\n";
+        assert_eq!(select("from=/fn b/", text), Some("fn b() {\n}\n"));
+        assert_eq!(select("from=fn b", text), Some("fn b() {\n}\n"));
+        assert_eq!(select("from=/^fn/|to=/^}/", text), Some("fn a() {\n    x\n}\n"));
+        assert_eq!(select("to=/^}/", text), Some("fn a() {\n    x\n}\n"));
+        assert_eq!(select(r"from=/a\|b/|to=/^}/", text), Some("fn a() {\n    x\n}\n"));
+        // `to` is searched after the line matching `from`
+        assert_eq!(select("from=/fn/|to=/fn/", text), Some("fn a() {\n    x\n}\nfn b() {\n"));
+        assert_eq!(select("from=/x/|to=/x/", text), None);
+        assert_eq!(select("from=/missing/", text), None);
+        // within the line range
+        assert_eq!(select("lines=4-|from=/fn/", text), Some("fn b() {\n}\n"));
+        assert_eq!(select("lines=1-2|to=/^}/", text), None);
+    }
+
+    #[test]
+    fn describes_selection() {
+        let selection = LineSelection::from_tag(&tag("{{include|a.rs|to=/}/|lines=1-3|fence=rust}}")).unwrap().unwrap();
+        assert_eq!(selection.description(), "lines=1-3|to=/}/");
+    }
+
+    #[test]
+    fn dedents_common_indentation() {
+        assert_eq!(dedent("    a\n      b\n\n    c\n"), "a\n  b\n\nc\n");
+        assert_eq!(dedent("\ta\n\t\tb\n"), "a\n\tb\n");
+        assert_eq!(dedent("a\n  b\n"), "a\n  b\n");
+    }
+
+    #[test]
+    fn fences_longer_than_backticks_in_text() {
+        assert_eq!(fence("x\n", "sh"), "```sh\nx\n```");
+        assert_eq!(fence("a ``` b", "rust"), "````rust\na ``` b\n````");
+        assert_eq!(fence("", "toml"), "```toml\n```");
+    }
+
+    #[test]
+    fn infers_languages() {
+        assert_eq!(language_of("RS"), Some("rust"));
+        assert_eq!(language_of("md"), None);
+    }
+}
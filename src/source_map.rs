@@ -0,0 +1,119 @@
+use std::ops::Range;
+use std::path::Path;
+use crate::error::Location;
+
+/// tracks how the text of a file is rewritten by the preprocessors, so that the tags found in the rewritten text
+/// can be located in the file.
+pub struct SourceMap {
+    /// the text before any tag is expanded.
+    original: Option<String>,
+    /// the edits made by each pass over the text, in order.
+    passes: Vec<Vec<Edit>>,
+}
+
+/// the text at `replaced` was replaced with a text of `length` bytes.
+pub struct Edit {
+    pub replaced: Range<usize>,
+    pub length: usize,
+}
+
+impl SourceMap {
+    pub const fn new() -> Self {
+        Self { original: None, passes: vec![] }
+    }
+
+    /// remembers `text` as the original text, unless one is already remembered.
+    pub fn start(&mut self, text: &str) {
+        self.original.get_or_insert_with(|| text.to_string());
+    }
+
+    /// records the edits of a pass, which are sorted by the offsets in the text before the pass.
+    pub fn record(&mut self, edits: Vec<Edit>) {
+        if !edits.is_empty() {
+            self.passes.push(edits);
+        }
+    }
+
+    /// locates `offset` of `current`, which is the text after the recorded passes.
+    /// an offset in the text inserted by a pass is located at the start of the text replaced by it.
+    pub fn locate(&self, file: &Path, current: &str, offset: usize) -> Location {
+        self.original.as_ref().map_or_else(
+            || Location::from_offset(file, current, offset),
+            |original| {
+                let offset = self.passes.iter().rev().fold(offset, |offset, edits| original_offset(edits, offset));
+                Location::from_offset(file, original, offset.min(original.len()))
+            },
+        )
+    }
+}
+
+/// maps `offset` in the text after `edits` to the offset in the text before them.
+fn original_offset(edits: &[Edit], offset: usize) -> usize {
+    let (mut before, mut after) = (0, 0);
+    for edit in edits {
+        let start = after + (edit.replaced.start - before);
+        if offset < start {
+            break
+        }
+        if offset < start + edit.length {
+            return edit.replaced.start
+        }
+        before = edit.replaced.end;
+        after = start + edit.length;
+    }
+
+    before + (offset - after)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn position(location: &Location) -> (usize, usize) {
+        (location.line, location.column)
+    }
+
+    #[test]
+    fn locates_in_current_text_without_original() {
+        let map = SourceMap::new();
+        let location = map.locate(Path::new("a.md"), "ab\ncd", 4);
+        assert_eq!(location.file, Path::new("a.md"));
+        assert_eq!(position(&location), (2, 2));
+    }
+
+    #[test]
+    fn locates_after_a_tag_is_expanded() {
+        let mut map = SourceMap::new();
+        map.start("ab {{x}} cd\n{{y}}\n");
+        // `{{x}}` is replaced with `LONG TEXT`
+        map.record(vec![Edit { replaced: 3..8, length: 9 }]);
+        let current = "ab LONG TEXT cd\n{{y}}\n";
+
+        assert_eq!(position(&map.locate(Path::new("a.md"), current, 0)), (1, 1));
+        assert_eq!(position(&map.locate(Path::new("a.md"), current, 13)), (1, 10));
+        assert_eq!(position(&map.locate(Path::new("a.md"), current, 16)), (2, 1));
+        // in the inserted text
+        assert_eq!(position(&map.locate(Path::new("a.md"), current, 5)), (1, 4));
+    }
+
+    #[test]
+    fn locates_through_several_passes() {
+        let mut map = SourceMap::new();
+        map.start("ab {{x}} cd\n{{y}}\n");
+        map.record(vec![Edit { replaced: 3..8, length: 9 }]);
+        // the first line is removed
+        map.record(vec![Edit { replaced: 0..16, length: 0 }]);
+        map.record(vec![]);
+
+        assert_eq!(position(&map.locate(Path::new("a.md"), "{{y}}\n", 0)), (2, 1));
+        assert_eq!(position(&map.locate(Path::new("a.md"), "{{y}}\n", 2)), (2, 3));
+    }
+
+    #[test]
+    fn keeps_the_first_original() {
+        let mut map = SourceMap::new();
+        map.start("a\nb");
+        map.start("zzzz");
+        assert_eq!(position(&map.locate(Path::new("a.md"), "zzzz", 2)), (2, 1));
+    }
+}
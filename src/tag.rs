@@ -0,0 +1,347 @@
+//! tokenizer of the `{{name|argument|key=value}}` tag syntax, which is shared by every preprocessor.
+//!
+//! - the first segment is the name of the tag.
+//! - the following segments are positional arguments, or options if they look like `key=value`.
+//! - `\{`, `\}` and `\|` are taken literally in segments.
+//! - `\{{` outside of tags is not a start of a tag. it is written as `{{` in the final output.
+//! - tags in code blocks, code spans and HTML comments are not recognized.
+
+use std::ops::Range;
+use crate::context::BuildContext;
+use crate::error::{Error, ErrorKind};
+use crate::markdown::verbatim_ranges;
+use crate::source_map::Edit;
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Tag {
+    pub name: String,
+    pub arguments: Vec<String>,
+    /// `key=value` segments, in the order of appearance.
+    pub options: Vec<(String, String)>,
+    /// byte range of the whole tag, from `{{` to `}}`, in the parsed text.
+    pub span: Range<usize>,
+}
+
+impl Tag {
+    #[must_use]
+    pub fn argument(&self, index: usize) -> Option<&str> {
+        self.arguments.get(index).map(String::as_str)
+    }
+
+    /// the value of the last option named `key`.
+    #[must_use]
+    pub fn option(&self, key: &str) -> Option<&str> {
+        self.options.iter().rev().find(|(k, _)| k == key).map(|(_, v)| v.as_str())
+    }
+}
+
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub enum Node {
+    /// byte range of the text between tags.
+    Text(Range<usize>),
+    Tag(Tag),
+}
+
+/// splits `text` into tags and the text between them.
+/// something which looks like a start of a tag but is not closed on the same line is treated as text.
+#[must_use]
+pub fn parse(text: &str) -> Vec<Node> {
+    scan(text).0
+}
+
+/// the `{{` in `text` which look like starts of tags but are not parsed as tags, such as `{{include|a.md}`.
+///
+/// each of them is returned with its offset and the name written after it. the ones without name are omitted.
+#[must_use]
+pub fn broken_tags(text: &str) -> Vec<(usize, &str)> {
+    name_broken_tags(text, scan(text).1)
+}
+
+/// pairs the offsets of `{{` in `text` which failed to be parsed with the names written after them.
+fn name_broken_tags(text: &str, starts: Vec<usize>) -> Vec<(usize, &str)> {
+    starts.into_iter()
+        .filter_map(|start| {
+            let rest = &text[start + 2..];
+            let name = rest[..rest.find(['|', '{', '}', '\n']).unwrap_or(rest.len())].trim();
+            (!name.is_empty()).then_some((start, name))
+        })
+        .collect()
+}
+
+/// the error for the `{{` at `start` of `content`, which starts the tag `name` but is not parsed as a tag.
+#[must_use]
+pub fn broken_tag_error(build_context: &BuildContext<'_>, content: &str, start: usize, name: &str) -> Error {
+    Error::from(ErrorKind::BadTagSyntax { tag: name.to_string(), expected: "`}}` which closes the tag on the same line" })
+        .at(|| build_context.locate(content, start))
+}
+
+/// splits `text` into nodes as [`parse`] does, and also returns the offsets of the `{{` which failed to be parsed.
+fn scan(text: &str) -> (Vec<Node>, Vec<usize>) {
+    let verbatim = verbatim_ranges(text);
+    let mut verbatim = verbatim.iter().peekable();
+    let mut nodes = vec![];
+    let mut broken = vec![];
+    let mut text_start = 0;
+    let mut position = 0;
+    while position < text.len() {
+        while verbatim.next_if(|range| range.end <= position).is_some() {}
+        let rest = &text[position..];
+        if let Some(range) = verbatim.next_if(|range| range.start <= position) {
+            position = range.end;
+        } else if rest.starts_with("\\{") {
+            position += 2;
+        } else if rest.starts_with("{{") {
+            let next_verbatim = verbatim.peek().map_or(text.len(), |range| range.start);
+            if let Some(tag) = parse_tag(text, position).filter(|tag| tag.span.end <= next_verbatim) {
+                if text_start < position {
+                    nodes.push(Node::Text(text_start..position));
+                }
+                position = tag.span.end;
+                text_start = position;
+                nodes.push(Node::Tag(tag));
+            } else {
+                broken.push(position);
+                position += 1;
+            }
+        } else {
+            position += rest.chars().next().map_or(1, char::len_utf8);
+        }
+    }
+
+    if text_start < text.len() {
+        nodes.push(Node::Text(text_start..text.len()));
+    }
+
+    (nodes, broken)
+}
+
+/// parses the tag which starts at `start`.
+fn parse_tag(text: &str, start: usize) -> Option<Tag> {
+    let mut segments = vec![String::new()];
+    let mut chars = text[start + 2..].char_indices().peekable();
+    while let Some((offset, c)) = chars.next() {
+        match c {
+            '\\' if matches!(chars.peek(), Some((_, '{' | '}' | '|'))) => {
+                let (_, escaped) = chars.next()?;
+                segments.last_mut()?.push(escaped);
+            }
+            '}' if matches!(chars.peek(), Some((_, '}'))) => {
+                return build_tag(segments, start..start + 2 + offset + 2);
+            }
+            '{' if matches!(chars.peek(), Some((_, '{'))) => return None,
+            '\n' => return None,
+            '|' => segments.push(String::new()),
+            c => segments.last_mut()?.push(c),
+        }
+    }
+
+    None
+}
+
+fn build_tag(segments: Vec<String>, span: Range<usize>) -> Option<Tag> {
+    let mut segments = segments.into_iter();
+    let name = segments.next()?.trim().to_string();
+    if name.is_empty() {
+        return None
+    }
+
+    let mut arguments = vec![];
+    let mut options = vec![];
+    for segment in segments {
+        match option(&segment) {
+            Some((key, value)) => options.push((key.to_string(), value.trim().to_string())),
+            None => arguments.push(segment.trim().to_string()),
+        }
+    }
+
+    Some(Tag { name, arguments, options, span })
+}
+
+/// splits `segment` into a key and a value if it looks like `key=value`.
+fn option(segment: &str) -> Option<(&str, &str)> {
+    let (key, value) = segment.split_once('=')?;
+    let key = key.trim();
+    let mut key_chars = key.chars();
+    let valid_key = key_chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && key_chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.');
+
+    valid_key.then_some((key, value))
+}
+
+/// replaces every tag named `name` in `content` with the result of `replacement`.
+/// other tags and the text between tags are left as is.
+///
+/// # Errors
+/// fails at the first replacement which fails, or at the first `{{name` which is not closed. the location of the tag
+/// in `build_context` is attached to the error.
+/// if `build_context` is checking, the failure is reported to it and the tag is removed instead.
+pub fn replace_tags(build_context: &BuildContext<'_>, content: &str, name: &str, mut replacement: impl FnMut(&Tag) -> Result<String, Error>) -> Result<String, Error> {
+    let (nodes, broken) = scan(content);
+    let mut broken = name_broken_tags(content, broken).into_iter()
+        .filter_map(|(start, tag)| (tag == name).then_some(start))
+        .peekable();
+    let mut replaced = String::with_capacity(content.len());
+    let mut edits = vec![];
+    for node in nodes {
+        match node {
+            Node::Tag(tag) if tag.name == name => {
+                match replacement(&tag).map_err(|e| e.at(|| build_context.locate(content, tag.span.start))) {
+                    Ok(expanded) => {
+                        edits.push(Edit { replaced: tag.span, length: expanded.len() });
+                        replaced.push_str(&expanded);
+                    }
+                    Err(e) if build_context.is_checking() => {
+                        // the tag is removed, so that it is not expanded again in the file including this file.
+                        build_context.report(e);
+                        edits.push(Edit { replaced: tag.span, length: 0 });
+                    }
+                    Err(e) => return Err(e),
+                }
+            }
+            Node::Tag(Tag { span, .. }) => replaced.push_str(&content[span]),
+            Node::Text(span) => {
+                let mut last = span.start;
+                while let Some(start) = broken.next_if(|start| *start < span.end) {
+                    let e = broken_tag_error(build_context, content, start, name);
+                    if !build_context.is_checking() {
+                        return Err(e)
+                    }
+                    // the tag is escaped, so that it is not reported again in the file including this file.
+                    build_context.report(e);
+                    replaced.push_str(&content[last..start]);
+                    replaced.push('\\');
+                    edits.push(Edit { replaced: start..start, length: 1 });
+                    last = start;
+                }
+                replaced.push_str(&content[last..span.end]);
+            }
+        }
+    }
+    build_context.record_edits(edits);
+
+    Ok(replaced)
+}
+
+/// escapes `{{` in `text` as `\{{`, so that the text inserted by a tag is not expanded as tags by the later
+/// preprocessors. [`unescape`] turns it back.
+#[must_use]
+pub fn escape(text: &str) -> String {
+    text.replace("{{", "\\{{")
+}
+
+/// turns escaped `\{{` into `{{`, except in code and comments.
+/// this is done once after every tag in the root document is expanded.
+#[must_use]
+pub fn unescape(content: &str) -> String {
+    let mut unescaped = String::with_capacity(content.len());
+    let mut last = 0;
+    for range in verbatim_ranges(content) {
+        unescaped.push_str(&content[last..range.start].replace("\\{{", "{{"));
+        unescaped.push_str(&content[range.clone()]);
+        last = range.end;
+    }
+    unescaped.push_str(&content[last..].replace("\\{{", "{{"));
+
+    unescaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(text: &str) -> Vec<Tag> {
+        parse(text).into_iter()
+            .filter_map(|node| match node {
+                Node::Tag(tag) => Some(tag),
+                Node::Text(_) => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn splits_text_and_tags() {
+        let nodes = parse("a {{include|./x.md|shift=1}} b");
+        assert_eq!(nodes, vec![
+            Node::Text(0..2),
+            Node::Tag(Tag {
+                name: "include".to_string(),
+                arguments: vec!["./x.md".to_string()],
+                options: vec![("shift".to_string(), "1".to_string())],
+                span: 2..28,
+            }),
+            Node::Text(28..30),
+        ]);
+    }
+
+    #[test]
+    fn spans_are_byte_offsets() {
+        let tags = tags("é{{a}}");
+        assert_eq!(tags[0].span, 2..7);
+    }
+
+    #[test]
+    fn escapes_are_taken_literally_in_segments() {
+        assert_eq!(tags(r"{{include|a\|b\}c\{d}}")[0].arguments, vec!["a|b}c{d"]);
+        assert_eq!(tags(r"{{include|C:\dir}}")[0].arguments, vec![r"C:\dir"]);
+    }
+
+    #[test]
+    fn escaped_start_is_not_a_tag() {
+        assert!(tags(r"\{{include|a.md}}").is_empty());
+        assert_eq!(unescape(r"\{{x}} `\{{y}}`"), r"{{x}} `\{{y}}`");
+        assert_eq!(escape(r"{{x}} \{{y}}"), r"\{{x}} \\{{y}}");
+        assert_eq!(unescape(&escape(r"{{x}} \{{y}}")), r"{{x}} \{{y}}");
+    }
+
+    #[test]
+    fn distinguishes_options_from_arguments() {
+        let tags = tags("{{t|a=b| c = d |1=e|=f|plain|x.y-z=1|url=http://a?b=c}}");
+        assert_eq!(tags[0].options, vec![
+            ("a".to_string(), "b".to_string()),
+            ("c".to_string(), "d".to_string()),
+            ("x.y-z".to_string(), "1".to_string()),
+            ("url".to_string(), "http://a?b=c".to_string()),
+        ]);
+        assert_eq!(tags[0].arguments, vec!["1=e", "=f", "plain"]);
+        assert_eq!(tags[0].option("url"), Some("http://a?b=c"));
+    }
+
+    #[test]
+    fn last_option_wins() {
+        let tags = tags("{{t|k=1|k=2}}");
+        assert_eq!(tags[0].option("k"), Some("2"));
+        assert_eq!(tags[0].option("missing"), None);
+    }
+
+    #[test]
+    fn unterminated_tags_are_text() {
+        for text in ["{{include|a.md", "{{include|a.md}", "{{include|a.md}\n}}", "{{ |a}}"] {
+            assert_eq!(parse(text), vec![Node::Text(0..text.len())], "{text}");
+        }
+    }
+
+    #[test]
+    fn finds_broken_tags() {
+        let text = "{{include|a.md}\n{{link or include|a.md\n{{ if mode=static }\n{{}}\n`{{x|`\n\\{{y|\n{{ok}}";
+        assert_eq!(broken_tags(text), vec![(0, "include"), (16, "link or include"), (39, "if mode=static")]);
+        assert_eq!(broken_tags("{{include|./{{var|dir}}/b.md}}"), vec![(0, "include")]);
+    }
+
+    #[test]
+    fn tag_is_not_opened_inside_another_tag() {
+        assert_eq!(parse("{{a|{{b}}}}"), vec![
+            Node::Text(0..4),
+            Node::Tag(Tag { name: "b".to_string(), arguments: vec![], options: vec![], span: 4..9 }),
+            Node::Text(9..11),
+        ]);
+    }
+
+    #[test]
+    fn tags_in_code_are_text() {
+        let names = tags("`{{a}}` {{b}}\n\n```\n{{c}}\n```\n<!-- {{d}} -->\n{{e|`x}}`")
+            .into_iter()
+            .map(|tag| tag.name)
+            .collect::<Vec<_>>();
+        assert_eq!(names, vec!["b"]);
+    }
+}
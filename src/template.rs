@@ -0,0 +1,86 @@
+//! templates of the text which replaces a tag, instead of the content of the included file.
+
+/// placeholders which need the content of the included file.
+const CONTENT_PLACEHOLDERS: [&str; 2] = ["{title}", "{content}"];
+
+/// `{name}` in a template is replaced with the value of the placeholder `name`:
+///
+/// - `{file_name}`: the file name of the included file.
+/// - `{path}`: the path written in the tag, which is relative to the including document.
+/// - `{link}`: `{path}` formatted as a destination of Markdown link. the fragment is kept only if it is a heading
+///   anchor of the file, because a region is not an anchor.
+/// - `{full_path}`: the path of the included file resolved from the including document.
+/// - `{title}`: the text of the first heading in the included text, or the file name if there is no heading.
+/// - `{content}`: the included text.
+///
+/// other text, including braces which are not placeholders, is left as is.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct Templates {
+    /// renders the tags whose strategy is `link`.
+    pub link: String,
+    /// renders the tags whose strategy is `details-collapsed`.
+    pub details: String,
+}
+
+impl Default for Templates {
+    fn default() -> Self {
+        Self {
+            link: "This section is migrated. Please see [{file_name}]({link})".to_string(),
+            details: "<details><summary>content of {full_path}</summary>\n\n{content}\n</details>".to_string(),
+        }
+    }
+}
+
+/// whether `template` uses the content of the included file.
+#[must_use]
+pub fn needs_content(template: &str) -> bool {
+    CONTENT_PLACEHOLDERS.iter().any(|placeholder| template.contains(placeholder))
+}
+
+/// replaces the placeholders in `template` with `values`, in a single pass so that the values are not replaced again.
+#[must_use]
+pub fn fill(template: &str, values: &[(&str, &str)]) -> String {
+    let mut filled = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        filled.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let value = rest[1..].find('}').and_then(|end| {
+            let name = &rest[1..=end];
+            values.iter().find(|(key, _)| *key == name).map(|(_, value)| (end + 2, value))
+        });
+        if let Some((length, value)) = value {
+            filled.push_str(value);
+            rest = &rest[length..];
+        } else {
+            filled.push('{');
+            rest = &rest[1..];
+        }
+    }
+    filled.push_str(rest);
+
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fills_placeholders_once() {
+        let values = [("title", "{path}"), ("path", "a.md")];
+        assert_eq!(fill("[{title}]({path})", &values), "[{path}](a.md)");
+        assert_eq!(fill("{unknown} {path} {", &values), "{unknown} a.md {");
+        assert_eq!(fill("{{path}}", &values), "{a.md}");
+        assert_eq!(fill("é{path}", &values), "éa.md");
+    }
+
+    #[test]
+    fn needs_content_only_for_its_placeholders() {
+        let templates = Templates::default();
+        assert!(!needs_content(&templates.link));
+        assert!(needs_content(&templates.details));
+        assert!(needs_content("See {title}."));
+        assert!(!needs_content("See {file_name} at {full_path}."));
+    }
+}
@@ -0,0 +1,53 @@
+//! helpers for the tests which read files.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use crate::context::BuildContext;
+use crate::error::Error;
+use crate::mode::BuildMode;
+use crate::pipeline::Pipeline;
+use crate::process;
+
+/// a temporary directory, which is removed with the files in it when dropped.
+pub struct TempDir {
+    path: PathBuf,
+}
+
+impl TempDir {
+    pub fn new() -> Self {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let name = format!("mdtpp-test-{}-{}", std::process::id(), COUNT.fetch_add(1, Ordering::Relaxed));
+        let path = std::env::temp_dir().join(name);
+        std::fs::create_dir_all(&path).unwrap();
+
+        Self { path: path.canonicalize().unwrap() }
+    }
+
+    /// writes `content` to `relative_path` in the directory, creating the parent directories.
+    pub fn write(&self, relative_path: &str, content: &str) -> PathBuf {
+        let path = self.path.join(relative_path);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(&path, content).unwrap();
+
+        path
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.path);
+    }
+}
+
+/// expands the file `input_file` with the built-in pipeline in the built-in mode `mode`.
+pub fn expand(input_file: &Path, mode: &str) -> Result<String, Error> {
+    let mode = BuildMode::builtin(mode).unwrap();
+    let pipeline = Pipeline::builtin();
+    let context = BuildContext::new(&mode, input_file, &pipeline);
+
+    process(std::fs::read_to_string(input_file).unwrap(), &context)
+}
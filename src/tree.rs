@@ -0,0 +1,79 @@
+//! lists the files of a documentation tree, to preprocess the whole tree at once.
+
+use std::path::{Path, PathBuf};
+use glob::Pattern;
+use crate::error::{Error, ErrorKind};
+use crate::front_matter::front_matter;
+use crate::markdown::is_markdown;
+
+/// the front matter key which marks a file as a fragment that is only included from other documents.
+pub const PARTIAL_KEY: &str = "partial";
+
+/// how a file of the tree is built.
+#[derive(Copy, Clone, Eq, PartialEq, Debug)]
+pub enum EntryKind {
+    /// Markdown file, which is preprocessed.
+    Document,
+    /// any other file, which is copied as is.
+    Asset,
+}
+
+/// a file of the tree and the path it is written to.
+#[derive(Clone, Eq, PartialEq, Debug)]
+pub struct TreeEntry {
+    pub source: PathBuf,
+    pub destination: PathBuf,
+    pub kind: EntryKind,
+}
+
+/// lists the files under `input_dir` with the paths they are written to under `output_dir`.
+///
+/// files matching any of `exclude` (relative to `input_dir`) and Markdown files with `partial: true` in their
+/// front matter are skipped. `output_dir` is skipped if it is in `input_dir`.
+///
+/// # Errors
+/// fails if the directories or the Markdown files can not be read.
+pub fn walk_tree(input_dir: &Path, output_dir: &Path, exclude: &[Pattern]) -> Result<Vec<TreeEntry>, Error> {
+    let output_dir_canonical = output_dir.canonicalize().ok();
+    let mut entries = vec![];
+    let mut pending = vec![input_dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let mut children = std::fs::read_dir(&dir)
+            .and_then(|children| children.map(|child| child.map(|child| child.path())).collect::<Result<Vec<_>, _>>())
+            .map_err(|e| ErrorKind::from_io(&dir, e))?;
+        children.sort();
+
+        for child in children {
+            let relative = child.strip_prefix(input_dir).unwrap_or(&child);
+            if exclude.iter().any(|pattern| pattern.matches_path(relative)) {
+                continue
+            }
+
+            if child.is_dir() {
+                if output_dir_canonical.is_none() || child.canonicalize().ok() != output_dir_canonical {
+                    pending.push(child);
+                }
+                continue
+            }
+
+            let kind = if is_markdown(&child) {
+                let content = std::fs::read_to_string(&child).map_err(|e| ErrorKind::from_io(&child, e))?;
+                if front_matter(&content).is_some_and(|front_matter| front_matter.flag(PARTIAL_KEY)) {
+                    continue
+                }
+                EntryKind::Document
+            } else {
+                EntryKind::Asset
+            };
+
+            entries.push(TreeEntry {
+                destination: output_dir.join(relative),
+                source: child,
+                kind,
+            });
+        }
+    }
+
+    entries.sort_by(|a, b| a.source.cmp(&b.source));
+    Ok(entries)
+}
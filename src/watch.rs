@@ -0,0 +1,43 @@
+//! detects changes of files by polling their modification time, which works without any service on every platform.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::SystemTime;
+
+#[derive(Default)]
+pub struct Watcher {
+    /// the modification time of each watched file when it was seen last. `None` if it did not exist.
+    modified: HashMap<PathBuf, Option<SystemTime>>,
+}
+
+fn modified(path: &PathBuf) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+impl Watcher {
+    #[must_use]
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// starts watching `paths`. the files which are already watched are left as is, so that their changes since the
+    /// last poll are not missed.
+    pub fn watch(&mut self, paths: impl IntoIterator<Item = PathBuf>) {
+        for path in paths {
+            self.modified.entry(path).or_insert_with_key(modified);
+        }
+    }
+
+    /// the watched files which are modified, created or removed since the last poll.
+    pub fn changed(&mut self) -> Vec<PathBuf> {
+        self.modified.iter_mut()
+            .filter_map(|(path, last_modified)| {
+                let current = modified(path);
+                (current != *last_modified).then(|| {
+                    *last_modified = current;
+                    path.clone()
+                })
+            })
+            .collect()
+    }
+}